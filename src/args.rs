@@ -1,5 +1,13 @@
+use regex::Regex;
 use std::path::PathBuf;
 use structopt::StructOpt;
+use time::OffsetDateTime;
+use twitch_clip_downloader::broadcaster::BroadcasterRef;
+use twitch_clip_downloader::clip_download::ListingOptions;
+use twitch_clip_downloader::clip_download::SortKey;
+use twitch_clip_downloader::filter::ClipFilter;
+use twitch_clip_downloader::quality::QualityPreference;
+use twitch_clip_downloader::timestamp;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -15,43 +23,262 @@ pub struct Args {
     #[structopt(long, parse(from_os_str))]
     pub config: Option<PathBuf>,
 
+    /// Rendition to download: best, worst, or <height>[p<fps>] such as 720p60,
+    /// the nearest available is used if the clip is not available in it. Needs the 'playback'
+    /// resolver in the config file, the default 'thumbnail' resolver only finds one rendition
+    #[structopt(long, default_value = "best")]
+    pub quality: QualityPreference,
+
     #[structopt(subcommand)]
     pub command: Commands,
 }
 
+// The option structs flattened into these commands (`ListingArgs`, `FilterArgs`,
+// `SelectionOptions`) are described in plain comments. Structopt would use a doc comment on them as
+// the about text of every command they are flattened into.
 #[derive(Debug, StructOpt)]
 pub enum Commands {
     /// Only Check Authentication up to date
     #[structopt(name = "auth")]
     CheckAuth,
-    /// Get clip info for all clips associated with a streamer
+    /// Get clip info for all clips associated with a streamer, or with a game across all streamers
     ClipInfo {
-        /// The account name of the streamer
-        user: String,
+        /// The streamer, as a login name, numeric id, or twitch.tv/<name> url
+        #[structopt(required_unless = "game")]
+        user: Option<BroadcasterRef>,
+
+        /// List the clips of this game (name or id) instead, most viewed first
+        #[structopt(long, conflicts_with = "user")]
+        game: Option<String>,
 
         /// ClipInfo File to store info in
-        /// If not provided, stored in 'clip_info/<user>.json'
+        /// If not provided, stored in 'clip_info/<user>.json' or 'clip_info/game-<id>.json'
         #[structopt(long, parse(from_os_str))]
         clips: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        listing: ListingArgs,
     },
     /// Get the download link for clips
     /// must provide either user or clips, clips take precedence
     DownloadLinks {
-        /// The account name of the streamer
-        user: Option<String>,
+        /// The streamer, as a login name, numeric id, or twitch.tv/<name> url
+        user: Option<BroadcasterRef>,
 
         /// ClipInfo file, defaults to 'clip_info/<user>.json'
         #[structopt(long, parse(from_os_str))]
         clips: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        selection: SelectionOptions,
     },
     /// Download Clips
-    /// must provide either user or clips, clips take precedence
+    /// must provide either user, a single --game or clips, clips take precedence. Without a user
+    /// the clips of the game are listed across all streamers into 'clip_info/game-<id>.json' and
+    /// saved in 'clips/game-<id>/', combine with --sort views --limit N for the top clips
     DownloadClips {
-        /// User whos clips are to be downloaded
-        user: Option<String>,
+        /// User whos clips are to be downloaded, as a login name, numeric id, or twitch.tv/<name> url
+        user: Option<BroadcasterRef>,
+
+        /// ClipInfo file, defaults to 'clip_info/<user>.json' or 'clip_info/game-<id>.json'
+        #[structopt(long, parse(from_os_str))]
+        clips: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        listing: ListingArgs,
+
+        #[structopt(flatten)]
+        filter: FilterArgs,
+
+        #[structopt(flatten)]
+        selection: SelectionOptions,
+
+        /// Number of clips to download at once, defaults to 'jobs' in the config file
+        #[structopt(long)]
+        jobs: Option<usize>,
+
+        /// Path clips are saved to within the download directory, defaults to 'name_template' in
+        /// the config file. Placeholders: {id} {title} {creator} {broadcaster} {game} (the game
+        /// id) {views} {duration} {vod_offset} {date} or {date:<format>} as in {date:%Y-%m-%d},
+        /// '/' starts a subdirectory
+        #[structopt(long)]
+        name_template: Option<String>,
+    },
+    /// Merge newly listed clips into the ClipInfo file and download only the missing ones
+    Sync {
+        /// The streamer, as a login name, numeric id, or twitch.tv/<name> url
+        user: BroadcasterRef,
 
         /// ClipInfo file, defaults to 'clip_info/<user>.json'
         #[structopt(long, parse(from_os_str))]
         clips: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        listing: ListingArgs,
+
+        /// Number of clips to download at once, defaults to 'jobs' in the config file
+        #[structopt(long)]
+        jobs: Option<usize>,
+
+        /// Path clips are saved to within the download directory, defaults to 'name_template' in
+        /// the config file. Placeholders: {id} {title} {creator} {broadcaster} {game} (the game
+        /// id) {views} {duration} {vod_offset} {date} or {date:<format>} as in {date:%Y-%m-%d},
+        /// '/' starts a subdirectory
+        #[structopt(long)]
+        name_template: Option<String>,
+    },
+    /// Sync every channel of the watchlist, sharing one auth token. The watchlist is a json file
+    /// of the form {"channels": [{"user": "<name>", "clips": "<ClipInfo file>", "output":
+    /// "<download dir>", "name_template": "...", "filter": {"min_views": 10, ...}, "windowed":
+    /// false}]}, where everything but "user" is optional
+    RunAll {
+        /// Watchlist file, defaults to 'watchlist.json'
+        #[structopt(long, parse(from_os_str))]
+        watchlist: Option<PathBuf>,
+
+        /// Number of channels synced at once
+        #[structopt(long, default_value = "1")]
+        parallel: usize,
+
+        /// Number of clips to download at once per channel, defaults to 'jobs' in the config file
+        #[structopt(long)]
+        jobs: Option<usize>,
+    },
+    /// Keep running, periodically listing the recent clips of every channel of the watchlist (see
+    /// run-all) and downloading new ones
+    Watch {
+        /// Watchlist file, defaults to 'watchlist.json'
+        #[structopt(long, parse(from_os_str))]
+        watchlist: Option<PathBuf>,
+
+        /// Seconds to wait between polls
+        #[structopt(long, default_value = "300")]
+        interval: u64,
+
+        /// Number of channels synced at once
+        #[structopt(long, default_value = "1")]
+        parallel: usize,
+
+        /// Number of clips to download at once per channel, defaults to 'jobs' in the config file
+        #[structopt(long)]
+        jobs: Option<usize>,
     },
+    /// Download specific clips by url or id, saved in 'clips/<broadcaster>/'
+    Clip {
+        /// Clip urls (clips.twitch.tv/<id> or twitch.tv/<channel>/clip/<id>) or ids
+        #[structopt(parse(try_from_str = twitch_clip_downloader::clip_download::parse_clip_id))]
+        clips: Vec<String>,
+
+        /// File with one clip url or id per line, lines starting with '#' are ignored
+        #[structopt(long, parse(from_os_str))]
+        from_file: Option<PathBuf>,
+
+        /// Number of clips to download at once, defaults to 'jobs' in the config file
+        #[structopt(long)]
+        jobs: Option<usize>,
+
+        /// Path clips are saved to within the download directory, defaults to 'name_template' in
+        /// the config file
+        #[structopt(long)]
+        name_template: Option<String>,
+    },
+}
+
+// Options choosing which of the clips to work on
+#[derive(Debug, Default, Clone, StructOpt)]
+pub struct SelectionOptions {
+    /// Sort clips by views (most first), date (newest first), duration (longest first) or title,
+    /// the order is kept in the ClipInfo file
+    #[structopt(long)]
+    pub sort: Option<SortKey>,
+
+    /// Only work on the first N clips (after sorting)
+    #[structopt(long)]
+    pub limit: Option<usize>,
+}
+
+// Options controlling how clips are listed from the api, see `ListingOptions`
+#[derive(Debug, Default, Clone, StructOpt)]
+pub struct ListingArgs {
+    /// Only list clips created at or after this time, as YYYY-MM-DD or an RFC 3339 timestamp.
+    /// download-clips only downloads the clips from then on when the ClipInfo file exists
+    #[structopt(long, parse(try_from_str = timestamp::parse))]
+    pub started_at: Option<OffsetDateTime>,
+
+    /// Only list clips created before this time, as YYYY-MM-DD or an RFC 3339 timestamp.
+    /// download-clips only downloads the clips until then when the ClipInfo file exists
+    #[structopt(long, parse(try_from_str = timestamp::parse))]
+    pub ended_at: Option<OffsetDateTime>,
+
+    /// List clips in date windows to get past the ~1000 clip limit of a single listing. Has no
+    /// effect when download-clips reads an existing ClipInfo file
+    #[structopt(long)]
+    pub windowed: bool,
+}
+
+impl From<ListingArgs> for ListingOptions {
+    fn from(args: ListingArgs) -> Self {
+        Self {
+            started_at: args.started_at,
+            ended_at: args.ended_at,
+            windowed: args.windowed,
+        }
+    }
+}
+
+// Restricts which clips are downloaded, see `ClipFilter`. Options that can be repeated match any
+// of their values, all given options have to match.
+#[derive(Debug, Default, Clone, StructOpt)]
+pub struct FilterArgs {
+    /// Only clips made by this user (display name or user id, case insensitive)
+    #[structopt(long = "creator")]
+    pub creators: Vec<String>,
+
+    /// Only clips whose title matches this regular expression
+    #[structopt(long, parse(try_from_str = Regex::new))]
+    pub title_regex: Option<Regex>,
+
+    /// Only clips with at least this many views
+    #[structopt(long)]
+    pub min_views: Option<u64>,
+
+    /// Only clips at least this many seconds long
+    #[structopt(long)]
+    pub min_duration: Option<f64>,
+
+    /// Only clips at most this many seconds long
+    #[structopt(long)]
+    pub max_duration: Option<f64>,
+
+    /// Only clips created at or after this time, as YYYY-MM-DD or an RFC 3339 timestamp
+    #[structopt(long, parse(try_from_str = timestamp::parse))]
+    pub since: Option<OffsetDateTime>,
+
+    /// Only clips created before this time, as YYYY-MM-DD or an RFC 3339 timestamp
+    #[structopt(long, parse(try_from_str = timestamp::parse))]
+    pub until: Option<OffsetDateTime>,
+
+    /// Only clips of this game, by name or id
+    #[structopt(long = "game")]
+    pub games: Vec<String>,
+
+    /// Only clips in this language (such as 'en')
+    #[structopt(long = "language")]
+    pub languages: Vec<String>,
+}
+
+impl From<FilterArgs> for ClipFilter {
+    fn from(args: FilterArgs) -> Self {
+        Self {
+            creators: args.creators,
+            title_regex: args.title_regex,
+            min_views: args.min_views,
+            min_duration: args.min_duration,
+            max_duration: args.max_duration,
+            since: args.since,
+            until: args.until,
+            games: args.games,
+            languages: args.languages,
+        }
+    }
 }
@@ -0,0 +1,156 @@
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::retry::{AttemptError, RetryPolicy};
+use crate::state::State;
+use log::{debug, info, trace, warn};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use std::path::PathBuf;
+use time::prelude::*;
+
+/// Where tokens are requested unless `Config::auth_base_url` says otherwise
+pub const AUTH_BASE_URL: &'static str = "https://id.twitch.tv/oauth2";
+
+/// Tokens expiring sooner than this are renewed before use
+const AUTH_REFRESH_MARGIN_SECONDS: i64 = 300;
+
+/// Answer of the token endpoint to a client credentials grant
+#[derive(Debug, Deserialize)]
+struct AuthResponse {
+    access_token: String,
+    /// Seconds until the token expires
+    expires_in: i64,
+}
+
+/// Error body the token endpoint answers failed requests with
+#[derive(Debug, Deserialize)]
+struct AuthError {
+    #[serde(default)]
+    message: String,
+}
+
+pub fn create_client_with_headers() -> Result<Client> {
+    info!("Creating Client.");
+    Ok(Client::builder().user_agent(crate::USER_AGENT).build()?)
+}
+
+pub fn create_request_auth_headers(config: &Config, state: &State) -> Result<HeaderMap> {
+    info!("Creating request Headers.");
+    let token = state
+        .auth_token
+        .as_ref()
+        .ok_or_else(|| Error::Auth(String::from("No access token available for requests")))?;
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| Error::Auth(String::from("Access token is not a valid header value")))?,
+    );
+    headers.insert(
+        "client-id",
+        HeaderValue::from_str(&config.client_id)
+            .map_err(|_| Error::Auth(String::from("Client id is not a valid header value")))?,
+    );
+    Ok(headers)
+}
+
+pub async fn update_auth(
+    config: &Config,
+    state: &mut State,
+    state_path: Option<PathBuf>,
+) -> Result<()> {
+    trace!("Checking on auth status");
+    if let Some(auth_timeout) = state.auth_timeout {
+        let now = time::OffsetDateTime::now_utc();
+        if auth_timeout > now + AUTH_REFRESH_MARGIN_SECONDS.seconds() {
+            trace!("Reusing Auth Token");
+            let mut remaining = auth_timeout - now;
+            info!(
+                "Time before re-auth: Days: {} HMS: {}-{}-{}",
+                remaining.whole_days(),
+                {
+                    remaining -= remaining.whole_days().days();
+                    remaining.whole_hours()
+                },
+                {
+                    remaining -= remaining.whole_hours().hours();
+                    remaining.whole_minutes()
+                },
+                {
+                    remaining -= remaining.whole_minutes().minutes();
+                    remaining.whole_seconds()
+                }
+            );
+            return Ok(()); // Return early if the timeout has not yet passed
+        }
+    }
+
+    info!("Getting new Auth token for account");
+    let auth_response = request_token(config).await?;
+
+    info!("Recieved Auth Response:\n{:#?}", &auth_response);
+    state.auth_token.replace(auth_response.access_token);
+    state
+        .auth_timeout
+        .replace(time::OffsetDateTime::now_utc() + auth_response.expires_in.seconds());
+
+    info!("Auth changed, writing into state");
+    crate::state::save(state, state_path)
+}
+
+/// Request an app access token with the client credentials in `config`, retrying according to
+/// `config.retry`
+async fn request_token(config: &Config) -> Result<AuthResponse> {
+    let url = format!("{}/token", config.auth_base_url.trim_end_matches('/'));
+    let query = [
+        ("client_id", config.client_id.as_str()),
+        ("client_secret", config.client_secret.as_str()),
+        ("grant_type", "client_credentials"),
+    ];
+    let retry = &config.retry;
+    let client = Client::new();
+
+    retry
+        .run("Token request", || {
+            attempt_token(&client, &url, &query, retry)
+        })
+        .await
+}
+
+async fn attempt_token(
+    client: &Client,
+    url: &str,
+    query: &[(&str, &str)],
+    retry: &RetryPolicy,
+) -> std::result::Result<AuthResponse, AttemptError> {
+    debug!("Requesting token from {}", url);
+    let res = client
+        .post(url)
+        .query(query)
+        .send()
+        .await
+        .map_err(|e| AttemptError::request(e, retry))?;
+
+    let status = res.status();
+    if status.is_success() {
+        return res.json().await.map_err(|e| {
+            AttemptError::fatal(Error::Parse(format!("response from {}: {}", url, e)))
+        });
+    }
+
+    let message = res
+        .json::<AuthError>()
+        .await
+        .map(|e| e.message)
+        .unwrap_or_default();
+    let error = match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            warn!("Bad Request: {}", &message);
+            Error::Auth(message)
+        }
+        status => Error::Api(format!("{} answered {}: {}", url, status, message)),
+    };
+    Err(AttemptError::status(status, error, retry))
+}
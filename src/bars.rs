@@ -0,0 +1,54 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::Arc;
+use twitch_clip_downloader::progress::{Progress, Task};
+
+/// Shows progress in the terminal, as a bar for tasks of known length and a spinner otherwise
+pub struct Bars {
+    spinner_style: ProgressStyle,
+    bar_style: ProgressStyle,
+}
+
+impl Bars {
+    pub fn new() -> Self {
+        Self {
+            spinner_style: ProgressStyle::default_spinner()
+                .tick_chars("/|\\—")
+                .template("| {spinner} | {wide_msg:.cyan} |"),
+            bar_style: ProgressStyle::default_bar()
+                .progress_chars("=> ")
+                .tick_chars("/|\\—")
+                .template(
+                    "| {spinner} | {msg:40.cyan/white} [{wide_bar:.green}] [ Eta:{eta:4} | {pos:>4}/{len:4} ] |",
+                ),
+        }
+    }
+}
+
+impl Progress for Bars {
+    fn task(&self, message: &str, len: Option<u64>) -> Arc<dyn Task> {
+        let bar = match len {
+            Some(len) => ProgressBar::new(len).with_style(self.bar_style.clone()),
+            None => ProgressBar::new_spinner().with_style(self.spinner_style.clone()),
+        };
+        bar.set_message(message);
+        bar.tick();
+        bar.enable_steady_tick(50);
+        Arc::new(Bar(bar))
+    }
+}
+
+struct Bar(ProgressBar);
+
+impl Task for Bar {
+    fn set_message(&self, message: &str) {
+        self.0.set_message(message);
+    }
+
+    fn advance(&self, steps: u64) {
+        self.0.inc(steps);
+    }
+
+    fn finish(&self, message: &str) {
+        self.0.finish_with_message(message);
+    }
+}
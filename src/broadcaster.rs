@@ -0,0 +1,144 @@
+use crate::error::{Error, Result};
+use crate::retry::RetryPolicy;
+use crate::session::Session;
+use crate::state::State;
+use log::*;
+use regex::Regex;
+use reqwest::{header::HeaderMap, Client};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// A broadcaster as given on the command line, before it has been resolved against the api
+#[derive(Debug, Clone, PartialEq)]
+pub enum BroadcasterRef {
+    /// Login name of the channel, always stored lowercase
+    Login(String),
+    /// Numeric broadcaster id
+    Id(String),
+}
+
+impl std::fmt::Display for BroadcasterRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BroadcasterRef::Login(login) => write!(f, "{}", login),
+            BroadcasterRef::Id(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl FromStr for BroadcasterRef {
+    type Err = &'static str;
+
+    /// Accepts a login name, a numeric broadcaster id, or a `twitch.tv/<name>` url
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let input = input.trim();
+
+        let url = Regex::new(r"^(?:https?://)?(?:www\.|m\.)?twitch\.tv/([^/?#]+)/?(?:[?#].*)?$")
+            .expect("Could not compile regex");
+        let name = match url.captures(input) {
+            Some(caps) => caps.get(1).map(|m| m.as_str()).unwrap_or(""),
+            None => input,
+        };
+
+        if name.is_empty() {
+            Err("Broadcaster must not be empty")
+        } else if name.chars().all(|c| c.is_ascii_digit()) {
+            Ok(BroadcasterRef::Id(name.to_string()))
+        } else if name.len() <= 25 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            Ok(BroadcasterRef::Login(name.to_ascii_lowercase()))
+        } else {
+            Err("Broadcaster must be a login name, numeric id, or twitch.tv/<name> url")
+        }
+    }
+}
+
+/// A broadcaster whose id is known
+#[derive(Debug, Clone, PartialEq)]
+pub struct Broadcaster {
+    /// Lowercase login name. Default file locations ('clip_info/<login>.json', 'clips/<login>/')
+    /// are named after it, so a channel given by id or by name shares them.
+    pub login: String,
+    pub id: String,
+}
+
+/// The broadcaster as resolved before, from the login -> id mapping cached in `state`
+pub fn cached(broadcaster: &BroadcasterRef, state: &State) -> Option<Broadcaster> {
+    let (login, id) = match broadcaster {
+        BroadcasterRef::Login(login) => state.broadcasters.get_key_value(login)?,
+        BroadcasterRef::Id(id) => (state.broadcasters)
+            .iter()
+            .find(|(_, cached)| *cached == id)?,
+    };
+    trace!("Using cached id {} for {}", id, login);
+    Some(Broadcaster {
+        login: login.clone(),
+        id: id.clone(),
+    })
+}
+
+/// Resolve a broadcaster to its id and login, using the login -> id mapping cached in state when
+/// possible. New mappings are added to the state, which the caller is responsible for saving.
+pub async fn resolve(
+    broadcaster: &BroadcasterRef,
+    client: &Client,
+    session: &mut Session<'_>,
+) -> Result<Broadcaster> {
+    if let Some(cached) = cached(broadcaster, session.state) {
+        return Ok(cached);
+    }
+
+    info!("Looking up broadcaster {}", broadcaster);
+    let (field, value) = match broadcaster {
+        BroadcasterRef::Login(login) => ("login", login),
+        BroadcasterRef::Id(id) => ("id", id),
+    };
+    let config = session.config;
+    let user = session
+        .request(|headers| {
+            request_user(
+                field,
+                value,
+                client,
+                &config.api_base_url,
+                headers,
+                &config.retry,
+            )
+        })
+        .await?;
+
+    let login = user.login.to_ascii_lowercase();
+    (session.state.broadcasters).insert(login.clone(), user.id.clone());
+    Ok(Broadcaster { login, id: user.id })
+}
+
+/// A user as returned by helix
+#[derive(Debug, Deserialize)]
+struct User {
+    id: String,
+    login: String,
+}
+
+/// Look up the user whose `field` (`login` or `id`) is `value`
+async fn request_user(
+    field: &'static str,
+    value: &str,
+    client: &Client,
+    api: &str,
+    headers: HeaderMap,
+    retry: &RetryPolicy,
+) -> Result<User> {
+    let query = [(field, value.to_string())];
+    let page: crate::helix::Page<User> =
+        crate::helix::get(client, api, headers, "users", &query, retry).await?;
+    match page.data.len() {
+        0 => Err(Error::NotFound(format!(
+            "no broadcaster with the {} {}",
+            field, value
+        ))),
+        1 => Ok(page.data.into_iter().next().expect("Invariant failed")),
+        n => Err(Error::Api(format!(
+            "{} broadcasters matched the {} {}, refusing to guess",
+            n, field, value
+        ))),
+    }
+}
@@ -1,20 +1,81 @@
-
-
+use crate::error::{Error, Result};
+use crate::progress::Task;
+use crate::retry::{AttemptError, RetryPolicy};
+use crate::session::Session;
+use crate::state::Pagination;
 use log::*;
+use regex::Regex;
 use reqwest::{header::HeaderMap, Client};
 use serde::{Deserialize, Serialize};
-use std::io::{Write};
+use std::io::{BufRead, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::prelude::*;
 use twitch_api_rs::request::get_clips::ClipsResponseItem;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Clips did not exist before this, so windowed listings without a start date begin here
+const CLIPS_LAUNCH_DATE: &'static str = "2016-05-01";
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ClipInfo {
+    /// Clip id (slug)
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     pub created_by: String,
+    #[serde(default)]
+    pub creator_id: String,
     pub created_date: String,
+    #[serde(default)]
+    pub broadcaster_id: String,
+    #[serde(default)]
+    pub broadcaster_name: String,
+    /// Id of the vod the clip was taken from, empty if the vod is unavailable
+    #[serde(default)]
+    pub video_id: String,
+    #[serde(default)]
+    pub game_id: String,
+    #[serde(default)]
+    pub language: String,
+    #[serde(default)]
+    pub view_count: u64,
+    /// Length of the clip in seconds
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub embed_url: String,
     pub thumbnail_url: String,
     pub video_url: Option<String>,
+    /// Name of the resolver that produced `video_url`, see `resolver::VideoUrlResolver`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_url_resolver: Option<String>,
+    /// Quality of the rendition `video_url` points to, such as 720p60, none if unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_quality: Option<String>,
+    /// Whether the clip was present in the download directory at the last sync
+    #[serde(default)]
+    pub downloaded: bool,
+    /// Where the clip was saved, clips keep this name even if the template or the other clips
+    /// change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_path: Option<PathBuf>,
+    /// Why the last attempt to download the clip failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_error: Option<String>,
+}
+
+impl ClipInfo {
+    /// Key used to match clips between listings, files written before clip ids were recorded
+    /// fall back to the thumbnail url which is unique per clip
+    pub fn key(&self) -> &str {
+        if self.id.is_empty() {
+            &self.thumbnail_url
+        } else {
+            &self.id
+        }
+    }
 }
 
 unsafe impl Send for ClipInfo {}
@@ -22,11 +83,56 @@ unsafe impl Send for ClipInfo {}
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Clips {
     pub clips: Vec<ClipInfo>,
+    /// How the clips were last sorted, kept when new clips are merged in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<SortKey>,
+}
+
+/// Orders clips can be sorted in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    /// Most viewed first
+    Views,
+    /// Newest first
+    Date,
+    /// Longest first
+    Duration,
+    /// Alphabetically by title
+    Title,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "views" => Ok(SortKey::Views),
+            "date" => Ok(SortKey::Date),
+            "duration" => Ok(SortKey::Duration),
+            "title" => Ok(SortKey::Title),
+            _ => Err("Expected one of views, date, duration or title"),
+        }
+    }
+}
+
+/// Outcome of merging a listing into known clips
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MergeReport {
+    /// Clips that were not known before
+    pub added: usize,
+    /// Known clips that are still listed
+    pub unchanged: usize,
+    /// Known clips within the listed date range that are no longer listed
+    pub removed: usize,
 }
 
 impl Default for Clips {
     fn default() -> Self {
-        Clips { clips: Vec::new() }
+        Clips {
+            clips: Vec::new(),
+            order: None,
+        }
     }
 }
 
@@ -34,136 +140,898 @@ impl Clips {
     pub fn with_capacity(capacity: usize) -> Self {
         Clips {
             clips: Vec::with_capacity(capacity),
+            order: None,
         }
     }
 
+    /// Sort the clips and remember the order. Ties are broken by date (newest first) and then by
+    /// id so the order is the same every time.
+    pub fn sort(&mut self, key: SortKey) {
+        use std::cmp::Ordering;
+
+        let newest_first = |a: &ClipInfo, b: &ClipInfo| {
+            b.created_date
+                .cmp(&a.created_date)
+                .then_with(|| a.key().cmp(b.key()))
+        };
+        self.clips.sort_by(|a, b| {
+            let primary = match key {
+                SortKey::Views => b.view_count.cmp(&a.view_count),
+                SortKey::Date => Ordering::Equal,
+                SortKey::Duration => b
+                    .duration
+                    .partial_cmp(&a.duration)
+                    .unwrap_or(Ordering::Equal),
+                SortKey::Title => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            };
+            primary.then_with(|| newest_first(a, b))
+        });
+        self.order = Some(key);
+    }
+
+    /// Merge a fresh listing into these clips by clip id.
+    ///
+    /// Known clips have their metadata updated but keep their download info, new clips are appended
+    /// (or sorted in, if the clips have an order), and clips missing from the listing are kept.
+    /// Missing clips only count as removed if they fall into the date range of `listing`, the
+    /// options the clips were listed with.
+    pub fn merge(&mut self, listed: Clips, listing: &ListingOptions) -> MergeReport {
+        let mut report = MergeReport::default();
+        let mut listed: std::collections::HashMap<String, ClipInfo> = listed
+            .clips
+            .into_iter()
+            .map(|clip| (clip.key().to_string(), clip))
+            .collect();
+
+        for clip in self.clips.iter_mut() {
+            if let Some(update) = listed.remove(clip.key()) {
+                *clip = ClipInfo {
+                    video_url: clip.video_url.take(),
+                    video_url_resolver: clip.video_url_resolver.take(),
+                    video_quality: clip.video_quality.take(),
+                    downloaded: clip.downloaded,
+                    saved_path: clip.saved_path.take(),
+                    download_error: clip.download_error.take(),
+                    ..update
+                };
+                report.unchanged += 1;
+            } else if listing.covers(clip) {
+                report.removed += 1;
+            }
+        }
+
+        // Hash map order is arbitrary, so append new clips oldest first
+        let mut added: Vec<ClipInfo> = listed.into_iter().map(|(_, clip)| clip).collect();
+        added.sort_by(|a, b| a.created_date.cmp(&b.created_date));
+        report.added = added.len();
+        self.clips.extend(added);
+
+        if let Some(order) = self.order {
+            self.sort(order);
+        }
+
+        report
+    }
+
     pub fn append_from_data(&mut self, data: Vec<ClipsResponseItem>) {
         for item in data {
             self.clips.push(ClipInfo {
+                id: item.id,
                 name: item.title,
                 created_by: item.creator_name,
+                creator_id: item.creator_id,
                 created_date: item.created_at,
+                broadcaster_id: item.broadcaster_id,
+                broadcaster_name: item.broadcaster_name,
+                video_id: item.video_id,
+                game_id: item.game_id,
+                language: item.language,
+                view_count: item.view_count as u64,
+                duration: item.duration as f64,
+                url: item.url,
+                embed_url: item.embed_url,
                 thumbnail_url: item.thumbnail_url,
                 video_url: None,
+                video_url_resolver: None,
+                video_quality: None,
+                downloaded: false,
+                saved_path: None,
+                download_error: None,
             });
         }
     }
 }
 
-pub async fn get_all_clip_info(user: String, client: &Client, headers: HeaderMap) -> Option<Clips> {
-    let mut clip_info: Clips = Clips::default();
+/// Get the id of a clip from its url (`clips.twitch.tv/<id>`, `twitch.tv/<channel>/clip/<id>`)
+/// or the id itself
+pub fn parse_clip_id(input: &str) -> std::result::Result<String, &'static str> {
+    let input = input.trim();
+    let url = Regex::new(
+        r"^(?:https?://)?(?:(?:www\.|m\.)?twitch\.tv/[^/]+/clip|clips\.twitch\.tv(?:/embed\?clip=)?)/?([A-Za-z0-9_-]+)(?:[/?#].*)?$",
+    )
+    .expect("Could not compile regex");
+    let id = match url.captures(input) {
+        Some(caps) => caps.get(1).map(|m| m.as_str()).unwrap_or(""),
+        None => input,
+    };
+
+    if !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(id.to_string())
+    } else {
+        Err("Expected a clip url or id")
+    }
+}
 
-    // Get user id
-    let user_id = {
-        use twitch_api_rs::request::get_channel_information::*;
-
-        match {
-            ChannelInformationRequest::builder()
-                .channel_name(user.clone())
-                .build()
-                .expect("Invariant failed")
-                .make_request(client, headers.clone())
-                .await
-        } {
-            Some(PossibleChannelInformationResponse::ChannelInformationResponse(channel_info)) => {
-                if channel_info.data.len() < 1 {
-                    error!("No user found by that name");
-                    return None;
-                } else if channel_info.data.len() > 1 {
-                    warn!("More than one user by that name, assuming first");
+/// Look up clips by id. Ids that do not exist are logged and skipped, if none exist at all
+/// `Error::NotFound` is returned.
+pub async fn get_clips_by_id(
+    ids: &[String],
+    client: &Client,
+    session: &mut Session<'_>,
+    retry: &RetryPolicy,
+) -> Result<Clips> {
+    let config = session.config;
+    let mut clips = Clips::with_capacity(ids.len());
+    for chunk in ids.chunks(crate::helix::MAX_PAGE_SIZE) {
+        let query: Vec<(&str, String)> = chunk.iter().map(|id| ("id", id.clone())).collect();
+        let page: crate::helix::Page<ClipsResponseItem> = session
+            .request(|headers| {
+                crate::helix::get(
+                    client,
+                    &config.api_base_url,
+                    headers,
+                    "clips",
+                    &query,
+                    retry,
+                )
+            })
+            .await?;
+        clips.append_from_data(page.data);
+    }
+
+    let found: std::collections::HashSet<&str> =
+        clips.clips.iter().map(|clip| clip.id.as_str()).collect();
+    for id in ids.iter().filter(|id| !found.contains(id.as_str())) {
+        warn!("No clip found with the id {}", id);
+    }
+
+    if clips.clips.is_empty() {
+        Err(Error::NotFound(String::from("none of the requested clips")))
+    } else {
+        Ok(clips)
+    }
+}
+
+/// Read back the clips saved by an interrupted listing, skipping any duplicated by a page that was
+/// written but not recorded in the state
+fn load_checkpoint(path: &PathBuf) -> Option<Clips> {
+    let file = std::fs::File::open(path).ok()?;
+    let reader = std::io::BufReader::new(file);
+
+    let mut seen = std::collections::HashSet::new();
+    let mut clips = Clips::default();
+    for line in reader.lines() {
+        let line = line.ok()?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<ClipInfo>(&line) {
+            Ok(clip) => {
+                if seen.insert(clip.id.clone()) {
+                    clips.clips.push(clip);
                 }
-                channel_info.data[0].id.clone()
             }
-            Some(PossibleChannelInformationResponse::BadRequest(err)) => {
-                error!("Invalid auth:\n{:#?}", err);
-                return None;
+            Err(e) => {
+                warn!(
+                    "Could not parse checkpointed clip, ignoring rest of checkpoint: {}",
+                    e
+                );
+                break;
             }
-            None => {
-                error!("Invalid request");
-                return None;
+        }
+    }
+    Some(clips)
+}
+
+/// Append newly listed clips to the checkpoint file, one json object per line
+fn append_checkpoint(path: &PathBuf, clips: &[ClipInfo]) -> std::io::Result<()> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    for clip in clips {
+        serde_json::to_writer(&mut writer, clip)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// Helix stops paginating a single clip listing after roughly this many clips
+pub const CLIP_LISTING_CAP: usize = 1000;
+/// Size of the windows a windowed listing starts with, before any bisection
+const INITIAL_WINDOW_DAYS: i64 = 30;
+/// Windows are not bisected below this size, anything over the cap within it is lost
+const MIN_WINDOW_SECONDS: i64 = 60;
+
+/// What a clip listing is for
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipSource {
+    /// Clips of a single broadcaster, by broadcaster id
+    Broadcaster(String),
+    /// Clips of a game across all broadcasters, by game id
+    Game(String),
+}
+
+/// Options controlling how clips are listed from the api
+#[derive(Debug, Default, Clone)]
+pub struct ListingOptions {
+    /// Only list clips created at or after this time
+    pub started_at: Option<time::OffsetDateTime>,
+    /// Only list clips created before this time. A listing with only a start runs until it starts.
+    pub ended_at: Option<time::OffsetDateTime>,
+    /// List clips in date windows to get past the ~1000 clip limit of a single listing, see
+    /// `get_windowed_clip_info`
+    pub windowed: bool,
+}
+
+impl ListingOptions {
+    /// Whether a listing with these options includes the clip, going by its creation date
+    pub fn covers(&self, clip: &ClipInfo) -> bool {
+        if self.started_at.is_none() && self.ended_at.is_none() {
+            return true;
+        }
+        match crate::timestamp::parse(&clip.created_date) {
+            Ok(created) => {
+                self.started_at
+                    .map(|start| created >= start)
+                    .unwrap_or(true)
+                    && self.ended_at.map(|end| created < end).unwrap_or(true)
             }
-        } // Return from match with userid
+            Err(_) => false,
+        }
+    }
+}
+
+/// A clip listing, optionally restricted to a date range
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipQuery {
+    pub source: ClipSource,
+    pub started_at: Option<time::OffsetDateTime>,
+    pub ended_at: Option<time::OffsetDateTime>,
+}
+
+impl ClipQuery {
+    pub fn new(source: ClipSource) -> Self {
+        Self {
+            source,
+            started_at: None,
+            ended_at: None,
+        }
+    }
+
+    /// Identifies the query in the state and in checkpoint file names
+    pub fn key(&self) -> String {
+        let source = match &self.source {
+            ClipSource::Broadcaster(id) => id.clone(),
+            ClipSource::Game(id) => format!("game-{}", id),
+        };
+        match (self.started_at, self.ended_at) {
+            (None, None) => source,
+            (started_at, ended_at) => format!(
+                "{}_{}_{}",
+                source,
+                started_at.map(|t| t.unix_timestamp()).unwrap_or(0),
+                ended_at.map(|t| t.unix_timestamp()).unwrap_or(0)
+            ),
+        }
+    }
+}
+
+/// Request a single page of a query from the helix api at `api`, returning the clips and the
+/// cursor of the next page. Requests that could not be completed are retried according to `retry`.
+async fn request_page(
+    query: &ClipQuery,
+    after: Option<String>,
+    client: &Client,
+    api: &str,
+    headers: HeaderMap,
+    retry: &RetryPolicy,
+) -> Result<(Vec<ClipsResponseItem>, Option<String>)> {
+    info!("Making request with key {:#?}", &after);
+    let source = match &query.source {
+        ClipSource::Broadcaster(id) => ("broadcaster_id", id.clone()),
+        ClipSource::Game(id) => ("game_id", id.clone()),
     };
+    let mut params = vec![source, ("first", String::from("20"))];
+    if let Some(started_at) = query.started_at {
+        params.push(("started_at", crate::timestamp::format(started_at)));
+    }
+    if let Some(ended_at) = query.ended_at {
+        params.push(("ended_at", crate::timestamp::format(ended_at)));
+    }
+    if let Some(after) = after.filter(|after| !after.is_empty()) {
+        params.push(("after", after));
+    }
 
-    // Get all clips
+    let page: crate::helix::Page<ClipsResponseItem> =
+        crate::helix::get(client, api, headers, "clips", &params, retry).await?;
+    Ok((page.data, page.pagination.cursor))
+}
+
+/// List all clips matching a query.
+///
+/// After each page the clips are appended to `checkpoint` and the cursor is recorded in the state,
+/// so an interrupted listing continues where it stopped on the next run. Both are cleared once the
+/// listing completes.
+pub async fn get_all_clip_info(
+    query: ClipQuery,
+    client: &Client,
+    session: &mut Session<'_>,
+    checkpoint: PathBuf,
+    retry: &RetryPolicy,
+) -> Result<Clips> {
+    let config = session.config;
+    let mut clip_info: Clips = Clips::default();
+    let key = query.key();
+
+    // Resume from the previous run if it was interrupted
     let mut pagination: Option<String> = Some(String::from(""));
-    use twitch_api_rs::request::get_clips::*;
-    loop {
-        info!("Making request with key {:#?}", &pagination);
-        match {
-            ClipsRequest::builder()
-                .broadcaster_id(user_id.clone())
-                .after(pagination.take())
-                .first(Some(20))
-                .build()
-                .expect("Could not build request")
-                .make_request(&client, headers.clone())
-                .await
-        } {
-            Some(PossibleClipsResponse::ClipsResponse(resp)) => {
-                if resp.data.len() == 0 {
-                    return Some(clip_info);
+    match session.state.pagination.get(&key) {
+        Some(Pagination::Forwards(cursor)) => match load_checkpoint(&checkpoint) {
+            Some(clips) => {
+                info!(
+                    "Resuming clip listing for {} with {} clips already listed",
+                    &key,
+                    clips.clips.len()
+                );
+                clip_info = clips;
+                pagination = Some(cursor.clone());
+            }
+            None => {
+                warn!("Could not read clip listing checkpoint, starting over");
+                session.state.pagination.remove(&key);
+            }
+        },
+        Some(Pagination::Backwards(_)) => {
+            warn!("Cannot resume a backwards listing, starting over");
+            session.state.pagination.remove(&key);
+        }
+        Some(Pagination::Windowed(_)) => {
+            warn!("Cannot resume a windowed listing without --windowed, starting over");
+            session.state.pagination.remove(&key);
+        }
+        None => {}
+    }
+    if clip_info.clips.is_empty() {
+        // Anything left over here is not covered by a cursor
+        let _ = std::fs::remove_file(&checkpoint);
+        session.state.listing_ends.remove(&key);
+    }
+
+    // Without an end helix only lists the week after `started_at`. The end is fixed when the
+    // listing starts and kept when it is resumed, so every page covers the same range.
+    let mut query = query;
+    if query.started_at.is_some() && query.ended_at.is_none() {
+        let ended_at = (session.state.listing_ends)
+            .entry(key.clone())
+            .or_insert_with(time::OffsetDateTime::now_utc);
+        query.ended_at = Some(*ended_at);
+    }
+
+    // Once a page could not be checkpointed the recorded cursor stays before it, a resumed run
+    // would otherwise skip the missing page
+    let mut checkpointing = true;
+
+    // Get all clips
+    let complete = loop {
+        let after = pagination.take();
+        let page = session
+            .request(|headers| {
+                request_page(
+                    &query,
+                    after.clone(),
+                    client,
+                    &config.api_base_url,
+                    headers,
+                    retry,
+                )
+            })
+            .await;
+        match page {
+            Ok((data, cursor)) => {
+                if data.len() == 0 {
+                    break Ok(());
                 }
-                clip_info.append_from_data(resp.data);
+                let start = clip_info.clips.len();
+                clip_info.append_from_data(data);
 
-                if let Some(pag) = resp.pagination.cursor {
+                if let Some(pag) = cursor {
+                    if checkpointing {
+                        match append_checkpoint(&checkpoint, &clip_info.clips[start..]) {
+                            Ok(()) => {
+                                (session.state.pagination)
+                                    .insert(key.clone(), Pagination::Forwards(pag.clone()));
+                                crate::state::save(session.state, session.state_path.clone())?;
+                            }
+                            Err(e) => {
+                                warn!(
+                                    "Could not write clip listing checkpoint, an interrupted listing will resume from before this page: {}",
+                                    e
+                                );
+                                checkpointing = false;
+                            }
+                        }
+                    }
                     pagination = Some(pag);
                 } else {
-                    return Some(clip_info);
+                    break Ok(());
+                }
+            }
+            Err(e) => break Err(e),
+        };
+    };
+
+    if let Err(e) = complete {
+        warn!(
+            "Clip listing incomplete, run again to resume from {:?}",
+            &checkpoint
+        );
+        return Err(e);
+    }
+
+    let resumable = session.state.pagination.remove(&key).is_some();
+    let ended = session.state.listing_ends.remove(&key).is_some();
+    if resumable || ended {
+        crate::state::save(session.state, session.state_path.clone())?;
+    }
+    let _ = std::fs::remove_file(&checkpoint);
+    if clip_info.clips.len() >= CLIP_LISTING_CAP {
+        warn!(
+            "Listed {} clips, which is around the listing limit; use --windowed to make sure none were missed",
+            clip_info.clips.len()
+        );
+    }
+
+    Ok(clip_info)
+}
+
+/// List all clips matching a query by splitting its date range into windows, bisecting any window
+/// that reaches the listing cap. Without a start date the listing starts when clips were launched.
+///
+/// Windows are listed from the earliest on. After each window its clips are appended to
+/// `checkpoint` and the time listed up to is recorded in the state, so an interrupted listing
+/// continues with the next window on the next run. Both are cleared once the listing completes.
+pub async fn get_windowed_clip_info(
+    query: ClipQuery,
+    client: &Client,
+    session: &mut Session<'_>,
+    checkpoint: PathBuf,
+    retry: &RetryPolicy,
+) -> Result<Clips> {
+    use time::{Duration, OffsetDateTime};
+
+    let config = session.config;
+    let mut clip_info = Clips::default();
+    let key = query.key();
+
+    // Resume from the previous run if it was interrupted
+    let mut listed_until = None;
+    match session.state.pagination.get(&key) {
+        Some(Pagination::Windowed(until)) => {
+            match (crate::timestamp::parse(until), load_checkpoint(&checkpoint)) {
+                (Ok(until), Some(clips)) => {
+                    info!(
+                        "Resuming windowed clip listing for {} from {} with {} clips already listed",
+                        &key,
+                        crate::timestamp::format(until),
+                        clips.clips.len()
+                    );
+                    listed_until = Some(until);
+                    clip_info = clips;
+                }
+                _ => {
+                    warn!("Could not read clip listing checkpoint, starting over");
+                    session.state.pagination.remove(&key);
                 }
             }
-            Some(PossibleClipsResponse::BadRequest(err)) => {
-                error!("Invalid auth:\n{:#?}", err);
-                return Some(clip_info);
+        }
+        Some(_) => {
+            warn!("Cannot resume a listing that was not windowed in windows, starting over");
+            session.state.pagination.remove(&key);
+        }
+        None => {}
+    }
+    if listed_until.is_none() {
+        // Anything left over here is not covered by the state
+        let _ = std::fs::remove_file(&checkpoint);
+        session.state.listing_ends.remove(&key);
+    }
+
+    // The end is fixed when the listing starts and kept when it is resumed, so the windows cover
+    // the same range
+    let end = match query.ended_at {
+        Some(ended_at) => ended_at,
+        None => *(session.state.listing_ends)
+            .entry(key.clone())
+            .or_insert_with(OffsetDateTime::now_utc),
+    };
+    let start = listed_until
+        .or(query.started_at)
+        .unwrap_or_else(|| crate::timestamp::parse(CLIPS_LAUNCH_DATE).expect("Invariant failed"));
+
+    // Stack of windows still to list, earliest on top
+    let mut windows = Vec::new();
+    let mut window_start = start;
+    while window_start < end {
+        let window_end = std::cmp::min(window_start + Duration::days(INITIAL_WINDOW_DAYS), end);
+        windows.push((window_start, window_end));
+        window_start = window_end;
+    }
+    windows.reverse();
+
+    // Once a window could not be checkpointed the recorded time stays before it, a resumed run
+    // would otherwise skip the missing window
+    let mut checkpointing = true;
+
+    let mut seen: std::collections::HashSet<String> =
+        clip_info.clips.iter().map(|clip| clip.id.clone()).collect();
+    while let Some((window_start, window_end)) = windows.pop() {
+        let window = ClipQuery {
+            source: query.source.clone(),
+            started_at: Some(window_start),
+            ended_at: Some(window_end),
+        };
+        let length = window_end.unix_timestamp() - window_start.unix_timestamp();
+        let bisectable = length > MIN_WINDOW_SECONDS;
+
+        let mut listed = Clips::default();
+        let mut pagination: Option<String> = Some(String::from(""));
+        let capped = loop {
+            let after = pagination.take();
+            let page = session
+                .request(|headers| {
+                    request_page(
+                        &window,
+                        after.clone(),
+                        client,
+                        &config.api_base_url,
+                        headers,
+                        retry,
+                    )
+                })
+                .await;
+            let (data, cursor) = match page {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!(
+                        "Clip listing incomplete, run again to resume from {:?}",
+                        &checkpoint
+                    );
+                    return Err(e);
+                }
+            };
+            if data.len() == 0 {
+                break false;
             }
-            None => {
-                error!("Invalid request\n\n");
-                return Some(clip_info);
+            listed.append_from_data(data);
+
+            if listed.clips.len() >= CLIP_LISTING_CAP && bisectable {
+                break true;
+            }
+            match cursor {
+                Some(pag) => pagination = Some(pag),
+                None => break listed.clips.len() >= CLIP_LISTING_CAP,
             }
         };
+
+        if capped && bisectable {
+            let middle = window_start + Duration::seconds(length / 2);
+            debug!(
+                "Window {} - {} reached the listing cap, bisecting",
+                crate::timestamp::format(window_start),
+                crate::timestamp::format(window_end)
+            );
+            windows.push((middle, window_end));
+            windows.push((window_start, middle));
+            continue;
+        } else if capped {
+            warn!(
+                "Window starting {} reached the listing cap and cannot be split further, some clips may be missing",
+                crate::timestamp::format(window_start)
+            );
+        }
+
+        let start = clip_info.clips.len();
+        for clip in listed.clips {
+            if seen.insert(clip.id.clone()) {
+                clip_info.clips.push(clip);
+            }
+        }
+
+        // A listing that fits into a single window is not worth resuming
+        if checkpointing && !windows.is_empty() {
+            match append_checkpoint(&checkpoint, &clip_info.clips[start..]) {
+                Ok(()) => {
+                    let until = crate::timestamp::format(window_end);
+                    (session.state.pagination).insert(key.clone(), Pagination::Windowed(until));
+                    crate::state::save(session.state, session.state_path.clone())?;
+                }
+                Err(e) => {
+                    warn!(
+                        "Could not write clip listing checkpoint, an interrupted listing will resume from before this window: {}",
+                        e
+                    );
+                    checkpointing = false;
+                }
+            }
+        }
     }
+
+    let resumable = session.state.pagination.remove(&key).is_some();
+    let ended = session.state.listing_ends.remove(&key).is_some();
+    if resumable || ended {
+        crate::state::save(session.state, session.state_path.clone())?;
+    }
+    let _ = std::fs::remove_file(&checkpoint);
+
+    Ok(clip_info)
+}
+
+/// Extension of the file a clip is streamed into before it is complete, specific to this tool so
+/// part files of other programs are never mistaken for ours
+pub const PART_EXTENSION: &'static str = "clip-part";
+
+/// The file a clip is downloaded into until it is complete, `<path>.clip-part`
+pub fn part_path(path: &PathBuf) -> PathBuf {
+    let mut part = path.clone().into_os_string();
+    part.push(".");
+    part.push(PART_EXTENSION);
+    PathBuf::from(part)
 }
 
+/// Remove the part files of the clips saved at `paths` that are not among the `targets` being
+/// downloaded now, or whose clip has since been completed. Returns how many were removed.
+///
+/// Only the part files of exactly these paths are looked at, the download directory is not
+/// searched, so the partial downloads of clips that are not part of this run (another channel
+/// syncing into the same directory, clips left out by a limit) are left alone.
+pub fn clean_stale_parts(
+    paths: &[PathBuf],
+    targets: &std::collections::HashSet<PathBuf>,
+) -> Result<usize> {
+    let mut removed = 0;
+    for path in paths {
+        if targets.contains(path) && !path.exists() {
+            continue;
+        }
+
+        // Never follow a link out of the download directory
+        let part = part_path(path);
+        match std::fs::symlink_metadata(&part) {
+            Ok(metadata) if metadata.is_file() => {}
+            Ok(_) => continue,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(Error::Io(part, e)),
+        }
+
+        debug!("Removing stale part file {:?}", &part);
+        std::fs::remove_file(&part).map_err(Error::io(&part))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Parse a `Content-Range` header value into (first byte, complete length).
+/// The first byte is none for unsatisfied ranges (`bytes */<length>`).
+fn parse_content_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let value = value.trim().strip_prefix("bytes ")?;
+    let mut parts = value.splitn(2, '/');
+    let range = parts.next()?.trim();
+    let length = match parts.next()?.trim() {
+        "*" => None,
+        length => Some(length.parse().ok()?),
+    };
+    let first = if range == "*" {
+        None
+    } else {
+        Some(range.splitn(2, '-').next()?.parse().ok()?)
+    };
+    Some((first, length))
+}
+
+/// Download a clip into its part file (see `part_path`), moving it to `path` once complete.
+///
+/// Failed attempts are retried according to `retry`. If a part file is left over from an earlier
+/// attempt, only the remaining bytes are requested. Servers that ignore the range, or answer with a
+/// range that does not continue the part file, cause a full download instead.
 pub async fn download_clip(
     client: reqwest::Client,
     url: String,
     path: PathBuf,
-    bar: indicatif::ProgressBar,
-) -> Result<(), &'static str> {
+    retry: RetryPolicy,
+    task: Arc<dyn Task>,
+) -> Result<()> {
+    let res = retry
+        .run(&format!("Download of {:?}", &path), || {
+            attempt_download(&client, &url, &path, &retry)
+        })
+        .await;
+    if let Err(e) = &res {
+        error!("Could not download {:?}: {}\n", &path, e);
+    }
+    task.advance(1);
+    res
+}
+
+async fn attempt_download(
+    client: &Client,
+    url: &str,
+    path: &PathBuf,
+    retry: &RetryPolicy,
+) -> std::result::Result<(), AttemptError> {
+    use reqwest::{header, StatusCode};
+
+    let part = part_path(path);
+    let existing = match tokio::fs::metadata(&part).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+
+    let mut request = client.get(url);
+    if existing > 0 {
+        debug!("Resuming {:?} from byte {}", &part, existing);
+        request = request.header(header::RANGE, format!("bytes={}-", existing));
+    }
+
     // get response
-    let res = if let Ok(mut res) = client.get(&url).send().await {
-        // Try to open file
-        if let Ok(file) = tokio::fs::File::create(&path).await {
-            let mut writer = tokio::io::BufWriter::new(file);
-            // Write all bytes to file
-            loop {
-                match res.chunk().await {
-                    Ok(Some(bytes)) => {
-                        writer.write_all(&bytes).await;
-                    }
-                    Ok(None) => {
-                        writer.flush().await;
-                        break;
-                    }
-                    Err(e) => {
-                        error!("Could not get bytes from data: {:?}\n", e);
-                        return Err("Could not get bytes from response");
-                    }
-                }
+    let mut res = request
+        .send()
+        .await
+        .map_err(|e| AttemptError::request(e, retry))?;
+
+    let content_range = res
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_content_range);
+
+    // Work out whether the response continues the part file
+    let resume = if existing == 0 {
+        false
+    } else {
+        match (res.status(), content_range) {
+            (StatusCode::PARTIAL_CONTENT, Some((Some(first), length)))
+                if first == existing
+                    && match (length, res.content_length()) {
+                        (Some(length), Some(remaining)) => existing + remaining == length,
+                        _ => true,
+                    } =>
+            {
+                true
             }
-            Ok(())
-        } else {
-            error!("Could not open file for writing: {:?}\n", &path);
-            Err("Could not open file for writing")
+            (StatusCode::RANGE_NOT_SATISFIABLE, Some((None, Some(length))))
+                if length == existing =>
+            {
+                // The part file already holds the whole clip
+                return tokio::fs::rename(&part, path)
+                    .await
+                    .map_err(|e| AttemptError::fatal(Error::Io(path.clone(), e)));
+            }
+            (StatusCode::OK, _) => {
+                debug!(
+                    "Server ignored the range for {:?}, downloading in full",
+                    &part
+                );
+                // The response is the whole file, start over with it
+                false
+            }
+            (status @ StatusCode::PARTIAL_CONTENT, _)
+            | (status @ StatusCode::RANGE_NOT_SATISFIABLE, _) => {
+                debug!(
+                    "Server did not continue {:?} (status {}), downloading in full",
+                    &part, status
+                );
+                // A range that does not continue the part file is useless, get the whole file
+                res = client
+                    .get(url)
+                    .send()
+                    .await
+                    .map_err(|e| AttemptError::request(e, retry))?;
+                false
+            }
+            // Errors are handled below like those of a full download
+            _ => false,
         }
+    };
+
+    let status = res.status();
+    if !status.is_success() {
+        let error = match status {
+            StatusCode::NOT_FOUND | StatusCode::GONE => {
+                Error::NotFound(format!("clip file at {}", url))
+            }
+            status => Error::Network(format!("Server responded with {} for {}", status, url)),
+        };
+        return Err(AttemptError::status(status, error, retry));
+    }
+
+    // Bytes the part file should hold once the body is complete, if the server told us
+    let start = if resume { existing } else { 0 };
+    let expected = res.content_length().map(|remaining| start + remaining);
+
+    // Try to open file
+    let file = if resume {
+        tokio::fs::OpenOptions::new().append(true).open(&part).await
     } else {
-        if let Err(e) = client.get(&url).send().await {
-            error!("File error for {:?} with error {:#?}\n", &path, &e);
-            //error!("Did not work {:?}", &url);
-        }
-        Err("Could not make request")
+        tokio::fs::File::create(&part).await
     };
-    //error!("Worked {:?}", &url);
-    bar.inc(1);
-    res
+    let file = file.map_err(|e| AttemptError::fatal(Error::Io(part.clone(), e)))?;
+
+    // Write errors (full disk, lost permissions, ...) leave the part file behind so a later run can
+    // resume once the cause is fixed
+    let write_error = |e| AttemptError::fatal(Error::Io(part.clone(), e));
+
+    let mut writer = tokio::io::BufWriter::new(file);
+    let mut received = 0u64;
+    // Write all bytes to file
+    loop {
+        match res.chunk().await {
+            Ok(Some(bytes)) => {
+                writer.write_all(&bytes).await.map_err(write_error)?;
+                received += bytes.len() as u64;
+            }
+            Ok(None) => {
+                writer.flush().await.map_err(write_error)?;
+                // Make sure the data is on disk before the rename can make it look complete
+                writer.get_mut().sync_all().await.map_err(write_error)?;
+                break;
+            }
+            Err(e) => {
+                error!("Could not get bytes from data: {:?}\n", e);
+                // Keep what did arrive for the next attempt to resume from
+                let _ = writer.flush().await;
+                return Err(AttemptError::request(e, retry));
+            }
+        }
+    }
+
+    // Make sure the whole body made it to disk
+    let written = tokio::fs::metadata(&part).await.map_err(write_error)?.len();
+    if written != start + received {
+        return Err(AttemptError::fatal(Error::Io(
+            part.clone(),
+            std::io::Error::new(
+                std::io::ErrorKind::WriteZero,
+                format!(
+                    "wrote {} bytes but received {}",
+                    written.saturating_sub(start),
+                    received
+                ),
+            ),
+        )));
+    }
+    if let Some(expected) = expected {
+        if written != expected {
+            if written > expected {
+                // Can not be resumed from, start over next time
+                let _ = tokio::fs::remove_file(&part).await;
+            }
+            return Err(AttemptError {
+                retryable: true,
+                error: Error::Network(format!(
+                    "Received {} of {} bytes for {}",
+                    written, expected, url
+                )),
+            });
+        }
+    }
+
+    tokio::fs::rename(&part, path)
+        .await
+        .map_err(|e| AttemptError::fatal(Error::Io(path.clone(), e)))
 }
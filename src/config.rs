@@ -1,35 +1,120 @@
+use crate::error::{Error, Result};
+use crate::resolver::{self, ResolverConfig};
+use crate::retry::RetryPolicy;
 use log::error;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use std::path::PathBuf;
-use twitch_api_rs::Config;
-
-pub fn get_config(location: Option<PathBuf>) -> Option<Config> {
-    if let Ok(file) = File::open(location.unwrap_or(PathBuf::from(crate::DEFAULT_CONFIG_LOCATION)))
-    {
-        let buf_reader = BufReader::new(file);
-        match serde_json::from_reader(buf_reader) {
-            Ok(json) => json,
-            Err(e) => {
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Config {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Number of clips downloaded at once
+    #[serde(default = "default_jobs")]
+    pub jobs: usize,
+    /// Path clips are saved to within the download directory, see `naming::NameTemplate`
+    #[serde(default = "default_name_template")]
+    pub name_template: String,
+    /// How failed requests and downloads are retried
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Base url of the helix api
+    #[serde(default = "default_api_base_url")]
+    pub api_base_url: String,
+    /// Base url of the oauth2 endpoints tokens are requested from
+    #[serde(default = "default_auth_base_url")]
+    pub auth_base_url: String,
+    /// Base url clip videos are downloaded from instead of the host in their video url, such as a
+    /// caching proxy. The path and query of the video url are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cdn_base_url: Option<String>,
+    /// How the video url of each clip is found, tried in order until one succeeds
+    #[serde(default = "resolver::default_resolvers")]
+    pub resolvers: Vec<ResolverConfig>,
+}
+
+fn default_jobs() -> usize {
+    10
+}
+
+fn default_name_template() -> String {
+    String::from(crate::naming::DEFAULT_NAME_TEMPLATE)
+}
+
+fn default_api_base_url() -> String {
+    String::from(crate::helix::HELIX_BASE_URL)
+}
+
+fn default_auth_base_url() -> String {
+    String::from(crate::auth::AUTH_BASE_URL)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            client_id: String::new(),
+            client_secret: String::new(),
+            jobs: default_jobs(),
+            name_template: default_name_template(),
+            retry: RetryPolicy::default(),
+            api_base_url: default_api_base_url(),
+            auth_base_url: default_auth_base_url(),
+            cdn_base_url: None,
+            resolvers: resolver::default_resolvers(),
+        }
+    }
+}
+
+impl Config {
+    /// The url a clip video is downloaded from, `video_url` moved to `cdn_base_url` if one is set
+    pub fn cdn_url(&self, video_url: &str) -> Result<String> {
+        let base = match &self.cdn_base_url {
+            Some(base) => base,
+            None => return Ok(video_url.to_string()),
+        };
+        let url = Url::parse(video_url)
+            .map_err(|e| Error::Parse(format!("video url {:?}: {}", video_url, e)))?;
+
+        let mut rewritten = format!("{}{}", base.trim_end_matches('/'), url.path());
+        if let Some(query) = url.query() {
+            rewritten.push('?');
+            rewritten.push_str(query);
+        }
+        Ok(rewritten)
+    }
+}
+
+/// Read the configuration file, `Error::NotFound` if it does not exist yet
+pub fn get_config(location: Option<PathBuf>) -> Result<Config> {
+    let location = location.unwrap_or(PathBuf::from(crate::DEFAULT_CONFIG_LOCATION));
+    match File::open(&location) {
+        Ok(file) => {
+            let buf_reader = BufReader::new(file);
+            serde_json::from_reader(buf_reader).map_err(|e| {
                 error!("Could not parse configuration file:\n{:#?}", &e);
-                None
-            }
+                Error::Parse(format!("configuration file {:?}: {}", &location, e))
+            })
         }
-    } else {
-        None
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::NotFound(format!(
+            "configuration file {:?}",
+            &location
+        ))),
+        Err(e) => Err(Error::Io(location, e)),
     }
 }
 
-pub fn write_default(location: Option<PathBuf>) {
-    let file = File::create(location.unwrap_or(PathBuf::from(crate::DEFAULT_CONFIG_LOCATION)))
-        .expect("Could not create config file");
+/// Write the default configuration to `location` (or the default location), returning where it
+/// was written
+pub fn write_default(location: Option<PathBuf>) -> Result<PathBuf> {
+    let location = location.unwrap_or(PathBuf::from(crate::DEFAULT_CONFIG_LOCATION));
+    let file = File::create(&location).map_err(Error::io(&location))?;
 
     let writer = BufWriter::new(file);
     serde_json::to_writer_pretty(writer, &Config::default())
-        .expect("Could not serialize default config into file");
+        .map_err(|e| Error::Io(location.clone(), e.into()))?;
 
-    println!(
-        "Created Basic Config file at {}, please fill in information",
-        crate::DEFAULT_CONFIG_LOCATION
-    );
+    Ok(location)
 }
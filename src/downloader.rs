@@ -0,0 +1,726 @@
+use crate::broadcaster::{self, Broadcaster, BroadcasterRef};
+use crate::clip_download::{self, ClipInfo, ClipQuery, ClipSource, Clips, ListingOptions};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::filter::ClipFilter;
+use crate::game::{self, Game};
+use crate::naming::NameTemplate;
+use crate::progress::{Progress, Silent, Task};
+use crate::quality::QualityPreference;
+use crate::resolver::ResolverChain;
+use crate::session::Session;
+use crate::state::{self, State};
+use crate::watchlist::QueuedChannel;
+use log::*;
+use rayon::prelude::*;
+use reqwest::Client;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use time::prelude::*;
+
+/// Polls list clips from this long before the previous poll, clips can show up in listings late
+const POLL_OVERLAP_SECONDS: i64 = 3600;
+
+/// Lists, resolves and downloads clips.
+///
+/// The state (token, cached broadcaster ids, unfinished listings) is kept in memory and saved to
+/// `state_path` as it changes. Clones share the state, so a downloader can be handed to several
+/// tasks at once; listings take turns at the state while downloads run alongside each other.
+#[derive(Clone)]
+pub struct ClipDownloader {
+    config: Arc<Config>,
+    client: Client,
+    state: Arc<tokio::sync::Mutex<State>>,
+    state_path: Option<PathBuf>,
+    progress: Arc<dyn Progress>,
+    resolvers: Arc<ResolverChain>,
+    quality: QualityPreference,
+    checkpoint_dir: PathBuf,
+}
+
+impl ClipDownloader {
+    /// Progress is discarded until `with_progress` is used, video urls are resolved by the
+    /// resolvers listed in the config and point to the best available rendition, and partial
+    /// listings are kept in the default clip info directory
+    pub fn new(config: Config, state: State, state_path: Option<PathBuf>) -> Result<Self> {
+        let client = crate::auth::create_client_with_headers()?;
+        let resolvers = ResolverChain::from_config(&config.resolvers, &client, &config.retry);
+        Ok(Self {
+            config: Arc::new(config),
+            client,
+            state: Arc::new(tokio::sync::Mutex::new(state)),
+            state_path,
+            progress: Arc::new(Silent),
+            resolvers: Arc::new(resolvers),
+            quality: QualityPreference::default(),
+            checkpoint_dir: PathBuf::from(crate::DEFAULT_CLIP_INFO_LOCATION),
+        })
+    }
+
+    /// Report progress of listings and downloads to `progress`
+    pub fn with_progress(mut self, progress: Arc<dyn Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Resolve video urls with `resolvers` instead of those listed in the config
+    pub fn with_resolvers(mut self, resolvers: ResolverChain) -> Self {
+        self.resolvers = Arc::new(resolvers);
+        self
+    }
+
+    /// Download clips in the rendition `quality` prefers
+    pub fn with_quality(mut self, quality: QualityPreference) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Keep the clips of unfinished listings in `dir` until the listing completes
+    pub fn with_checkpoint_dir(mut self, dir: PathBuf) -> Self {
+        self.checkpoint_dir = dir;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Get a new token if there is none or the current one is about to expire
+    pub async fn authenticate(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+        crate::auth::update_auth(&self.config, &mut state, self.state_path.clone()).await
+    }
+
+    /// Look up a broadcaster given by login or id, see `broadcaster::resolve`
+    pub async fn resolve_broadcaster(&self, user: &BroadcasterRef) -> Result<Broadcaster> {
+        let mut state = self.state.lock().await;
+        self.resolve_broadcaster_with(user, &mut state).await
+    }
+
+    /// Login the files of a broadcaster are named after. Only broadcasters given by an id that
+    /// has not been resolved before are looked up, which needs a token.
+    pub async fn broadcaster_login(&self, user: &BroadcasterRef) -> Result<String> {
+        if let BroadcasterRef::Login(login) = user {
+            return Ok(login.clone());
+        }
+        let cached = broadcaster::cached(user, &*self.state.lock().await);
+        match cached {
+            Some(broadcaster) => Ok(broadcaster.login),
+            None => {
+                self.authenticate().await?;
+                Ok(self.resolve_broadcaster(user).await?.login)
+            }
+        }
+    }
+
+    /// List the clips of a broadcaster
+    pub async fn list_clips(
+        &self,
+        broadcaster: &Broadcaster,
+        listing: &ListingOptions,
+    ) -> Result<Clips> {
+        let mut state = self.state.lock().await;
+        self.list_broadcaster_clips(broadcaster, listing, &mut state)
+            .await
+    }
+
+    /// List the clips of a game across all broadcasters. Helix lists them most viewed first.
+    pub async fn list_game_clips(&self, game: &Game, listing: &ListingOptions) -> Result<Clips> {
+        let mut state = self.state.lock().await;
+        let task = self.progress.task("Retrieving Clips", None);
+        let mut session = Session::new(&self.config, &mut state, self.state_path.clone())?;
+
+        let source = ClipSource::Game(game.id.clone());
+        self.list_source(source, listing, &mut session, &*task)
+            .await
+    }
+
+    /// Resolve games given by name or id
+    pub async fn resolve_games(&self, games: &[String]) -> Result<Vec<Game>> {
+        let mut state = self.state.lock().await;
+        let mut session = Session::new(&self.config, &mut state, self.state_path.clone())?;
+
+        let mut resolved = Vec::with_capacity(games.len());
+        for game in games {
+            let game = game::resolve(game, &self.client, &mut session, &self.config.retry).await?;
+            debug!("Resolved game: {:?}", &game);
+            resolved.push(game);
+        }
+        Ok(resolved)
+    }
+
+    /// Look up clips by id, see `clip_download::get_clips_by_id`
+    pub async fn get_clips(&self, ids: &[String]) -> Result<Clips> {
+        let mut state = self.state.lock().await;
+        let mut session = Session::new(&self.config, &mut state, self.state_path.clone())?;
+        clip_download::get_clips_by_id(ids, &self.client, &mut session, &self.config.retry).await
+    }
+
+    async fn resolve_broadcaster_with(
+        &self,
+        user: &BroadcasterRef,
+        state: &mut State,
+    ) -> Result<Broadcaster> {
+        let task = self.progress.task("Resolving Broadcaster", None);
+        let mut session = Session::new(&self.config, state, self.state_path.clone())?;
+
+        let broadcaster = match broadcaster::resolve(user, &self.client, &mut session).await {
+            Ok(broadcaster) => broadcaster,
+            Err(e) => {
+                task.finish("Failed to resolve broadcaster");
+                return Err(e);
+            }
+        };
+        debug!("Resolved broadcaster: {:?}", &broadcaster);
+        task.finish(&format!("Resolved broadcaster {}", &broadcaster.login));
+
+        // Persist any newly resolved broadcaster ids
+        state::save(state, self.state_path.clone())?;
+        Ok(broadcaster)
+    }
+
+    async fn list_broadcaster_clips(
+        &self,
+        broadcaster: &Broadcaster,
+        listing: &ListingOptions,
+        state: &mut State,
+    ) -> Result<Clips> {
+        let task = self.progress.task("Retrieving Clips", None);
+        let mut session = Session::new(&self.config, state, self.state_path.clone())?;
+
+        let source = ClipSource::Broadcaster(broadcaster.id.clone());
+        self.list_source(source, listing, &mut session, &*task)
+            .await
+    }
+
+    async fn list_source(
+        &self,
+        source: ClipSource,
+        listing: &ListingOptions,
+        session: &mut Session<'_>,
+        task: &dyn Task,
+    ) -> Result<Clips> {
+        let query = ClipQuery {
+            source,
+            started_at: listing.started_at,
+            ended_at: listing.ended_at,
+        };
+        let retry = &self.config.retry;
+
+        task.set_message("Retrieving Clips");
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .create(&self.checkpoint_dir)
+            .map_err(Error::io(&self.checkpoint_dir))?;
+        let mut checkpoint = self.checkpoint_dir.clone();
+        checkpoint.push(query.key());
+        let checkpoint = checkpoint.with_extension(crate::PARTIAL_CLIP_INFO_EXTENSION);
+
+        let client = &self.client;
+        let ret = if listing.windowed {
+            clip_download::get_windowed_clip_info(query, client, session, checkpoint, retry).await
+        } else {
+            clip_download::get_all_clip_info(query, client, session, checkpoint, retry).await
+        };
+        match ret {
+            Ok(inner) => {
+                task.finish(&format!("Finished with {} items", inner.clips.len()));
+                Ok(inner)
+            }
+            Err(e) => {
+                task.finish("Failed to return data");
+                Err(e)
+            }
+        }
+    }
+
+    /// Fill in the url each clip is downloaded from
+    pub async fn create_download_links(&self, clips: Clips) -> Clips {
+        self.resolve_video_urls(clips, |_| true).await
+    }
+
+    /// Resolve the video urls of the clips `select` picks, `config.jobs` at a time
+    pub async fn resolve_video_urls(
+        &self,
+        mut clips: Clips,
+        select: impl Fn(&ClipInfo) -> bool,
+    ) -> Clips {
+        let queue: VecDeque<(usize, ClipInfo)> = (clips.clips)
+            .iter()
+            .enumerate()
+            .filter(|(_, clip)| select(clip))
+            .map(|(index, clip)| (index, clip.clone()))
+            .collect();
+
+        let task = self
+            .progress
+            .task("Creating download urls", Some(queue.len() as u64));
+
+        let resolvers = self.resolvers.clone();
+        let quality = self.quality;
+        let worker_task = task.clone();
+        let (returns, _) = run_queue(
+            queue,
+            self.config.jobs,
+            "Resolve",
+            move |(index, mut clip)| {
+                let resolvers = resolvers.clone();
+                let task = worker_task.clone();
+                async move {
+                    resolvers.resolve(&mut clip, &quality).await;
+                    task.advance(1);
+                    (index, clip)
+                }
+            },
+        )
+        .await;
+
+        // Only the renditions of resolvers that tell their quality can be chosen between
+        if self.quality != QualityPreference::Best {
+            let unknown = returns
+                .iter()
+                .filter(|(_, clip)| clip.video_url.is_some() && clip.video_quality.is_none())
+                .count();
+            if unknown > 0 {
+                warn!(
+                    "Could not apply the quality preference to {} clips, their resolver does not report qualities; add the playback resolver to the config to choose between them",
+                    unknown
+                );
+            }
+        }
+
+        // Clips a failed worker did not get to keep the url they had
+        for (index, clip) in returns {
+            clips.clips[index] = clip;
+        }
+
+        task.finish("Finished creating video urls");
+        clips
+    }
+
+    /// Download the clips `select` picks into `location`, returning the error each failed download
+    /// failed with keyed by clip. Names are worked out over all `clips`, see `NameTemplate::paths`.
+    pub async fn download(
+        &self,
+        clips: &Clips,
+        select: impl Fn(&ClipInfo) -> bool,
+        location: &PathBuf,
+        namer: &NameTemplate,
+        jobs: usize,
+    ) -> Result<HashMap<String, Error>> {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .create(location)
+            .map_err(Error::io(location))?;
+
+        let paths = namer.paths(location, &clips.clips);
+        let names: Vec<PathBuf> = paths.iter().flatten().cloned().collect();
+        let queue: VecDeque<_> = (clips.clips)
+            .iter()
+            .zip(paths.into_iter())
+            .filter(|(clip, _)| select(clip))
+            .filter_map(|(clip, loc)| Some((clip.key().to_string(), clip.video_url.clone()?, loc?)))
+            .collect();
+
+        let task = self
+            .progress
+            .task("Dowloading Clips", Some(queue.len() as u64));
+
+        // Templates may place clips in subdirectories
+        let parents: HashSet<PathBuf> = queue
+            .iter()
+            .filter_map(|(_, _, loc)| loc.parent().map(PathBuf::from))
+            .collect();
+        for parent in parents {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .create(&parent)
+                .map_err(Error::io(&parent))?;
+        }
+
+        // Part files of clips that are not being downloaded now would otherwise pile up forever
+        let targets: HashSet<PathBuf> = queue.iter().map(|(_, _, loc)| loc.clone()).collect();
+        let removed = clip_download::clean_stale_parts(&names, &targets)?;
+        if removed > 0 {
+            info!("Removed {} stale partial downloads", removed);
+        }
+
+        // Keep at most `jobs` downloads (each holding a socket and a file) in flight, requests were
+        // timing out with everything started at once
+        let client = self.client.clone();
+        let config = self.config.clone();
+        let worker_task = task.clone();
+        let (returns, left) = run_queue(
+            queue,
+            jobs,
+            "Download",
+            move |(clip_key, clip_url, clip_loc)| {
+                let client = client.clone();
+                let config = config.clone();
+                let task = worker_task.clone();
+                async move {
+                    let ret = match config.cdn_url(&clip_url) {
+                        Ok(clip_url) => {
+                            clip_download::download_clip(
+                                client,
+                                clip_url,
+                                clip_loc,
+                                config.retry.clone(),
+                                task,
+                            )
+                            .await
+                        }
+                        Err(e) => {
+                            task.advance(1);
+                            Err(e)
+                        }
+                    };
+                    (clip_key, ret)
+                }
+            },
+        )
+        .await;
+
+        let mut failures = HashMap::new();
+        for (clip_key, ret) in returns {
+            if let Err(e) = ret {
+                failures.insert(clip_key, e);
+            }
+        }
+        for (clip_key, _, _) in left {
+            failures.insert(
+                clip_key,
+                Error::Network(String::from("Download was not attempted")),
+            );
+        }
+
+        task.finish("Downloaded all clips");
+        Ok(failures)
+    }
+
+    /// Download the clips `select` picks, see `download`, and record the outcome on the clips:
+    /// where each clip in `location` was saved and why the selected clips that failed did. Pass
+    /// every clip of the ClipInfo file so the names do not depend on the selection.
+    pub async fn download_and_record(
+        &self,
+        clips: &mut Clips,
+        select: impl Fn(&ClipInfo) -> bool,
+        location: &PathBuf,
+        namer: &NameTemplate,
+        jobs: usize,
+    ) -> Result<HashMap<String, Error>> {
+        let selected: HashSet<String> = (clips.clips)
+            .iter()
+            .filter(|&clip| select(clip))
+            .map(|clip| clip.key().to_string())
+            .collect();
+        let failures = self
+            .download(
+                clips,
+                |clip| selected.contains(clip.key()),
+                location,
+                namer,
+                jobs,
+            )
+            .await?;
+
+        mark_downloaded(clips, location, namer);
+        for clip in (clips.clips).iter_mut() {
+            if selected.contains(clip.key()) {
+                clip.download_error = failures.get(clip.key()).map(|e| e.to_string());
+            }
+        }
+        Ok(failures)
+    }
+
+    /// Merge a listing, made with the options `listing`, into the ClipInfo file at `path`, then
+    /// download the clips matching `filter` that are missing from `location`. Clips not matching
+    /// the filter are kept in the file but not downloaded.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn sync(
+        &self,
+        listed: Clips,
+        listing: &ListingOptions,
+        path: &PathBuf,
+        location: &PathBuf,
+        filter: &ClipFilter,
+        namer: &NameTemplate,
+        jobs: usize,
+    ) -> Result<SyncReport> {
+        // Start from the existing clip info, if any
+        let mut known = read_clip_info(path)?.unwrap_or_default();
+
+        let merge = known.merge(listed, listing);
+
+        // Names are worked out over the whole file, so the filter does not change them
+        mark_downloaded(&mut known, location, namer);
+        let missing = |clip: &ClipInfo| filter.matches(clip) && !clip.downloaded;
+
+        // Only clips about to be downloaded need a (fresh, signed urls expire) video url
+        let mut known = self.resolve_video_urls(known, &missing).await;
+
+        let missing_count = known.clips.iter().filter(|&clip| missing(clip)).count();
+
+        info!("Downloading {} missing clips", missing_count);
+        let mut failures = self
+            .download(&known, &missing, location, namer, jobs)
+            .await?;
+
+        mark_downloaded(&mut known, location, namer);
+        let failed = known.clips.iter().filter(|&clip| missing(clip)).count();
+        for clip in known.clips.iter_mut().filter(|clip| filter.matches(clip)) {
+            clip.download_error = failures.remove(clip.key()).map(|e| e.to_string());
+        }
+
+        write_clip_info(path, &known)?;
+
+        Ok(SyncReport {
+            merge,
+            downloaded: missing_count.saturating_sub(failed),
+            failed,
+        })
+    }
+
+    /// Replace game names in the channel filters with their ids
+    pub async fn resolve_channel_games(
+        &self,
+        channels: &mut VecDeque<QueuedChannel>,
+    ) -> Result<()> {
+        for queued in channels.iter_mut() {
+            let filter = &mut queued.channel.filter;
+            if !filter.games.is_empty() {
+                let games = self.resolve_games(&filter.games).await?;
+                filter.games = games.into_iter().map(|game| game.id).collect();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sync a channel of the watchlist. The state is only held while listing.
+    ///
+    /// When `incremental` is set and the channel has been listed before, only clips created since
+    /// shortly before the last listing are listed. Clips that failed to download before are
+    /// retried either way.
+    pub async fn sync_channel(
+        &self,
+        queued: &QueuedChannel,
+        incremental: bool,
+        jobs: usize,
+    ) -> Result<SyncReport> {
+        let QueuedChannel {
+            user,
+            namer,
+            channel,
+            ..
+        } = queued;
+
+        let (broadcaster, listing, listed) = {
+            let mut state = self.state.lock().await;
+            let broadcaster = self.resolve_broadcaster_with(user, &mut state).await?;
+            let last_poll = state.last_poll.get(&broadcaster.login).cloned();
+            let listing = match last_poll {
+                // A short range like this fits into a single window, which is not checkpointed
+                Some(last_poll) if incremental => ListingOptions {
+                    started_at: Some(last_poll - POLL_OVERLAP_SECONDS.seconds()),
+                    ended_at: None,
+                    windowed: true,
+                },
+                _ => ListingOptions {
+                    windowed: channel.windowed,
+                    ..Default::default()
+                },
+            };
+
+            let polled = time::OffsetDateTime::now_utc();
+            let listed = self
+                .list_broadcaster_clips(&broadcaster, &listing, &mut state)
+                .await?;
+            state.last_poll.insert(broadcaster.login.clone(), polled);
+            state::save(&state, self.state_path.clone())?;
+            (broadcaster, listing, listed)
+        };
+
+        let path = (channel.clips.clone()).unwrap_or_else(|| clip_info_path(&broadcaster.login));
+        let location =
+            (channel.output.clone()).unwrap_or_else(|| download_location(&broadcaster.login));
+        let filter = &channel.filter;
+        self.sync(listed, &listing, &path, &location, filter, namer, jobs)
+            .await
+    }
+
+    /// Sync channels, `parallel` at a time, returning the outcome for each in watchlist order
+    pub async fn sync_channels(
+        &self,
+        channels: VecDeque<QueuedChannel>,
+        incremental: bool,
+        parallel: usize,
+        jobs: usize,
+    ) -> Vec<(usize, String, Result<SyncReport>)> {
+        let downloader = self.clone();
+        let (mut results, left) = run_queue(channels, parallel, "Channel", move |queued| {
+            let downloader = downloader.clone();
+            async move {
+                let ret = downloader.sync_channel(&queued, incremental, jobs).await;
+                (queued.index, queued.user.to_string(), ret)
+            }
+        })
+        .await;
+
+        for queued in left {
+            results.push((
+                queued.index,
+                queued.user.to_string(),
+                Err(Error::Network(String::from("Sync was not attempted"))),
+            ));
+        }
+        results.sort_by_key(|(index, _, _)| *index);
+        results
+    }
+}
+
+/// Work through `queue` with `workers` tasks, each taking the next item as soon as its current one
+/// is done so a slow item does not hold up the others. Returns the outputs, in no particular order,
+/// and the items that were never attempted because a worker failed.
+async fn run_queue<T, R, F, Fut>(
+    queue: VecDeque<T>,
+    workers: usize,
+    what: &str,
+    work: F,
+) -> (Vec<R>, Vec<T>)
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = R> + Send + 'static,
+{
+    let workers = std::cmp::max(1, std::cmp::min(workers, queue.len()));
+    let queue = Arc::new(Mutex::new(queue));
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let queue = queue.clone();
+            let work = work.clone();
+            tokio::task::spawn(async move {
+                let mut returns = Vec::new();
+                loop {
+                    let next = queue.lock().expect("Work queue poisoned").pop_front();
+                    match next {
+                        Some(item) => returns.push(work(item).await),
+                        None => break,
+                    }
+                }
+                returns
+            })
+        })
+        .collect();
+
+    let mut returns = Vec::new();
+    for handle in handles {
+        match handle.await {
+            Ok(worker_returns) => returns.extend(worker_returns),
+            Err(e) => error!("{} worker failed: {}", what, e),
+        }
+    }
+
+    let left = queue
+        .lock()
+        .expect("Work queue poisoned")
+        .drain(..)
+        .collect();
+    (returns, left)
+}
+
+/// Outcome of syncing a channel
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SyncReport {
+    pub merge: clip_download::MergeReport,
+    /// Clips downloaded during the sync
+    pub downloaded: usize,
+    /// Clips that should have been downloaded but are missing
+    pub failed: usize,
+}
+
+impl SyncReport {
+    pub fn add(&mut self, other: &SyncReport) {
+        self.merge.added += other.merge.added;
+        self.merge.unchanged += other.merge.unchanged;
+        self.merge.removed += other.merge.removed;
+        self.downloaded += other.downloaded;
+        self.failed += other.failed;
+    }
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} added, {} unchanged, {} removed, {} downloaded, {} failed",
+            self.merge.added,
+            self.merge.unchanged,
+            self.merge.removed,
+            self.downloaded,
+            self.failed
+        )
+    }
+}
+
+/// Default location of the clip info file for a user, 'clip_info/<user>.json'
+pub fn clip_info_path(user: &str) -> PathBuf {
+    let mut path = PathBuf::from(crate::DEFAULT_CLIP_INFO_LOCATION);
+    path.push(user);
+    path.with_extension("json")
+}
+
+/// Read a clip info file, none if it does not exist
+pub fn read_clip_info(path: &PathBuf) -> Result<Option<Clips>> {
+    match std::fs::File::open(path) {
+        Ok(file) => {
+            let reader = std::io::BufReader::new(file);
+            serde_json::from_reader(reader)
+                .map(Some)
+                .map_err(|e| Error::Parse(format!("clip info file {:?}: {}", path, e)))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::Io(path.clone(), e)),
+    }
+}
+
+pub fn write_clip_info(path: &PathBuf, clips: &Clips) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .create(parent)
+            .map_err(Error::io(parent))?;
+    }
+    let file = std::fs::File::create(path).map_err(Error::io(path))?;
+    let writer = std::io::BufWriter::new(file);
+    serde_json::to_writer_pretty(writer, clips).map_err(|e| Error::Io(path.clone(), e.into()))
+}
+
+/// Default directory clips of a user are downloaded to, 'clips/<user>/'
+pub fn download_location(user: &str) -> PathBuf {
+    let mut loc = PathBuf::from(crate::DEFAULT_DOWNLOAD_LOCATION);
+    loc.push(user);
+    loc
+}
+
+/// Set `downloaded` on each clip according to whether its file exists in `location`, recording
+/// where the clips that do exist were saved
+pub fn mark_downloaded(clips: &mut Clips, location: &PathBuf, namer: &NameTemplate) {
+    let paths = namer.paths(location, &clips.clips);
+    (clips.clips)
+        .par_iter_mut()
+        .zip(paths.into_par_iter())
+        .for_each(|(clip, path): (&mut ClipInfo, Option<PathBuf>)| {
+            // Clips downloaded before names were sanitized keep their old name
+            let path = path.map(|path| match namer.legacy_path(location, clip) {
+                Some(legacy) if !path.exists() && legacy.exists() => legacy,
+                _ => path,
+            });
+            clip.downloaded = path.as_ref().map(|path| path.exists()).unwrap_or(false);
+            if clip.downloaded {
+                clip.saved_path = path;
+            }
+        });
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Everything that can stop a run of the downloader.
+///
+/// Each kind maps to its own process exit code so scripts can tell failures apart:
+///
+/// | Code | Kind       | Cause                                                     |
+/// |------|------------|-----------------------------------------------------------|
+/// | 0    |            | Success                                                   |
+/// | 2    | `Auth`     | Credentials rejected or no token available                |
+/// | 3    | `Api`      | Twitch answered a request with an error                   |
+/// | 4    | `Network`  | A request could not be completed                          |
+/// | 5    | `Io`       | Reading or writing a local file failed                    |
+/// | 6    | `Parse`    | A config, state or clip info file could not be understood |
+/// | 7    | `NotFound` | A broadcaster, clip, or required file does not exist      |
+#[derive(Debug)]
+pub enum Error {
+    Auth(String),
+    Api(String),
+    Network(String),
+    Io(PathBuf, std::io::Error),
+    Parse(String),
+    NotFound(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Auth(_) => 2,
+            Error::Api(_) => 3,
+            Error::Network(_) => 4,
+            Error::Io(_, _) => 5,
+            Error::Parse(_) => 6,
+            Error::NotFound(_) => 7,
+        }
+    }
+
+    /// Attach the path an io error happened at
+    pub fn io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Error {
+        let path = path.into();
+        move |e| Error::Io(path, e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Auth(msg) => write!(f, "Authentication failed: {}", msg),
+            Error::Api(msg) => write!(f, "Twitch api error: {}", msg),
+            Error::Network(msg) => write!(f, "Network error: {}", msg),
+            Error::Io(path, e) => write!(f, "Could not access {:?}: {}", path, e),
+            Error::Parse(msg) => write!(f, "Could not parse {}", msg),
+            Error::NotFound(msg) => write!(f, "Not found: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Network(e.to_string())
+    }
+}
@@ -0,0 +1,134 @@
+use crate::clip_download::{ClipInfo, Clips};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use time::OffsetDateTime;
+
+/// Restricts which clips are downloaded. Options that can hold several values match any of them,
+/// all given options have to match.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClipFilter {
+    /// Only clips made by one of these users (display name or user id, case insensitive)
+    pub creators: Vec<String>,
+
+    /// Only clips whose title matches this regular expression
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub title_regex: Option<Regex>,
+
+    /// Only clips with at least this many views
+    pub min_views: Option<u64>,
+
+    /// Only clips at least this many seconds long
+    pub min_duration: Option<f64>,
+
+    /// Only clips at most this many seconds long
+    pub max_duration: Option<f64>,
+
+    /// Only clips created at or after this time, as YYYY-MM-DD or an RFC 3339 timestamp
+    #[serde(deserialize_with = "crate::timestamp::deserialize_option")]
+    pub since: Option<OffsetDateTime>,
+
+    /// Only clips created before this time, as YYYY-MM-DD or an RFC 3339 timestamp
+    #[serde(deserialize_with = "crate::timestamp::deserialize_option")]
+    pub until: Option<OffsetDateTime>,
+
+    /// Only clips of one of these games, by id
+    pub games: Vec<String>,
+
+    /// Only clips in one of these languages (such as 'en')
+    pub languages: Vec<String>,
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(regex) => Regex::new(&regex)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+impl ClipFilter {
+    pub fn is_empty(&self) -> bool {
+        self.creators.is_empty()
+            && self.title_regex.is_none()
+            && self.min_views.is_none()
+            && self.min_duration.is_none()
+            && self.max_duration.is_none()
+            && self.since.is_none()
+            && self.until.is_none()
+            && self.games.is_empty()
+            && self.languages.is_empty()
+    }
+
+    pub fn matches(&self, clip: &ClipInfo) -> bool {
+        let any = |values: &Vec<String>, value: &str| {
+            values.is_empty() || values.iter().any(|v| v.eq_ignore_ascii_case(value))
+        };
+
+        let creator =
+            any(&self.creators, &clip.created_by) || any(&self.creators, &clip.creator_id);
+        if !creator || !any(&self.games, &clip.game_id) {
+            return false;
+        }
+        if !any(&self.languages, &clip.language) {
+            return false;
+        }
+        if let Some(ref title_regex) = self.title_regex {
+            if !title_regex.is_match(&clip.name) {
+                return false;
+            }
+        }
+        if self
+            .min_views
+            .map(|min| clip.view_count < min)
+            .unwrap_or(false)
+        {
+            return false;
+        }
+        if self
+            .min_duration
+            .map(|min| clip.duration < min)
+            .unwrap_or(false)
+            || self
+                .max_duration
+                .map(|max| clip.duration > max)
+                .unwrap_or(false)
+        {
+            return false;
+        }
+
+        if self.since.is_some() || self.until.is_some() {
+            // Clips without a readable date can not be placed in the range
+            let created = match crate::timestamp::parse(&clip.created_date) {
+                Ok(created) => created,
+                Err(_) => return false,
+            };
+            if self.since.map(|since| created < since).unwrap_or(false)
+                || self.until.map(|until| created >= until).unwrap_or(false)
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Keep only the clips matching the filter
+    pub fn apply(&self, clips: Clips) -> Clips {
+        if self.is_empty() {
+            return clips;
+        }
+        Clips {
+            clips: clips
+                .clips
+                .into_iter()
+                .filter(|clip| self.matches(clip))
+                .collect(),
+            order: clips.order,
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::error::{Error, Result};
+use crate::retry::RetryPolicy;
+use crate::session::Session;
+use log::*;
+use reqwest::Client;
+use serde::Deserialize;
+
+/// A game (category) whose id is known
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Game {
+    pub id: String,
+    pub name: String,
+}
+
+impl Game {
+    /// The name used for default file locations ('clip_info/game-<id>.json', 'clips/game-<id>/')
+    pub fn key(&self) -> String {
+        format!("game-{}", &self.id)
+    }
+}
+
+/// Resolve a game given as a name or numeric id. Names are matched the way twitch does, which
+/// ignores case.
+pub async fn resolve(
+    game: &str,
+    client: &Client,
+    session: &mut Session<'_>,
+    retry: &RetryPolicy,
+) -> Result<Game> {
+    let game = game.trim();
+    if game.is_empty() {
+        return Err(Error::NotFound(String::from("game must not be empty")));
+    }
+    let param = if game.chars().all(|c| c.is_ascii_digit()) {
+        "id"
+    } else {
+        "name"
+    };
+
+    info!("Resolving game {}", game);
+    let query = [(param, game.to_string())];
+    let config = session.config;
+    let api = &config.api_base_url;
+    let page: crate::helix::Page<Game> = session
+        .request(|headers| crate::helix::get(client, api, headers, "games", &query, retry))
+        .await?;
+    match page.data.len() {
+        0 => Err(Error::NotFound(format!(
+            "no game with the {} {}",
+            param, game
+        ))),
+        1 => Ok(page.data.into_iter().next().expect("Invariant failed")),
+        n => Err(Error::Api(format!(
+            "{} games matched {}, refusing to guess",
+            n, game
+        ))),
+    }
+}
@@ -0,0 +1,86 @@
+use crate::error::{Error, Result};
+use crate::retry::{AttemptError, RetryPolicy};
+use log::*;
+use reqwest::{header::HeaderMap, Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/// Where helix requests go unless `Config::api_base_url` says otherwise
+pub const HELIX_BASE_URL: &'static str = "https://api.twitch.tv/helix";
+
+/// Most items helix returns for one request
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// One page of a helix response
+#[derive(Debug, Deserialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    #[serde(default)]
+    pub pagination: Cursor,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Cursor {
+    pub cursor: Option<String>,
+}
+
+/// Error body helix answers failed requests with
+#[derive(Debug, Deserialize)]
+struct HelixError {
+    #[serde(default)]
+    message: String,
+}
+
+/// Make a GET request to a helix endpoint (such as `clips`) below `base_url`, retrying according to
+/// `retry`
+pub async fn get<T: DeserializeOwned>(
+    client: &Client,
+    base_url: &str,
+    headers: HeaderMap,
+    endpoint: &str,
+    query: &[(&str, String)],
+    retry: &RetryPolicy,
+) -> Result<Page<T>> {
+    let url = format!("{}/{}", base_url.trim_end_matches('/'), endpoint);
+    retry
+        .run("Request", || {
+            attempt_get(client, &url, &headers, query, retry)
+        })
+        .await
+}
+
+async fn attempt_get<T: DeserializeOwned>(
+    client: &Client,
+    url: &str,
+    headers: &HeaderMap,
+    query: &[(&str, String)],
+    retry: &RetryPolicy,
+) -> std::result::Result<Page<T>, AttemptError> {
+    debug!("Requesting {} with {:?}", url, query);
+    let res = client
+        .get(url)
+        .headers(headers.clone())
+        .query(query)
+        .send()
+        .await
+        .map_err(|e| AttemptError::request(e, retry))?;
+
+    let status = res.status();
+    if status.is_success() {
+        return res.json().await.map_err(|e| {
+            AttemptError::fatal(Error::Parse(format!("response from {}: {}", url, e)))
+        });
+    }
+
+    let message = res
+        .json::<HelixError>()
+        .await
+        .map(|e| e.message)
+        .unwrap_or_default();
+    let error = match status {
+        StatusCode::UNAUTHORIZED => Error::Auth(message),
+        StatusCode::NOT_FOUND => Error::NotFound(format!("{} ({})", url, message)),
+        status => Error::Api(format!("{} answered {}: {}", url, status, message)),
+    };
+    Err(AttemptError::status(status, error, retry))
+}
@@ -0,0 +1,35 @@
+//! Listing and downloading of twitch clips.
+//!
+//! `ClipDownloader` covers authentication, listing clips of broadcasters or games, resolving
+//! their download urls and downloading them. Progress is reported through the `progress::Progress`
+//! trait so it can be shown (or ignored) by whatever uses the library.
+
+pub mod auth;
+pub mod broadcaster;
+pub mod clip_download;
+pub mod config;
+pub mod downloader;
+pub mod error;
+pub mod filter;
+pub mod game;
+mod helix;
+pub mod naming;
+pub mod progress;
+pub mod quality;
+pub mod resolver;
+pub mod retry;
+pub mod session;
+pub mod state;
+pub mod timestamp;
+pub mod watchlist;
+
+pub use downloader::{ClipDownloader, SyncReport};
+pub use error::{Error, Result};
+
+pub const USER_AGENT: &'static str = "TWITCH_CLIP_DOWNLOADER/0.1";
+pub const DEFAULT_CONFIG_LOCATION: &'static str = "config.json";
+pub const DEFAULT_STATE_LOCATION: &'static str = "state.json";
+pub const DEFAULT_CLIP_INFO_LOCATION: &'static str = "clip_info/";
+pub const DEFAULT_DOWNLOAD_LOCATION: &'static str = "clips/";
+pub const DEFAULT_WATCHLIST_LOCATION: &'static str = "watchlist.json";
+const PARTIAL_CLIP_INFO_EXTENSION: &'static str = "partial.jsonl";
@@ -1,261 +1,20 @@
 mod args;
-mod clip_download;
-mod config;
-mod state;
-
-use reqwest::Client;
-use reqwest::{
-    self,
-    header::{HeaderMap, HeaderValue},
-};
-use state::*;
+mod bars;
+
 use structopt::StructOpt;
+use twitch_clip_downloader::broadcaster::BroadcasterRef;
+use twitch_clip_downloader::downloader::{
+    clip_info_path, download_location, read_clip_info, write_clip_info,
+};
+use twitch_clip_downloader::{
+    clip_download, config, filter, naming, state, watchlist, ClipDownloader, Error, Result,
+    SyncReport,
+};
 
-use log::{debug, error, info, trace, warn};
+use log::{debug, info, trace, warn};
 use rayon::prelude::*;
-use regex::Regex;
-use std::path::PathBuf;
-use time::prelude::*;
-use twitch_api_rs::request::application_auth::*;
-
-const USER_AGENT: &'static str = "TWITCH_CLIP_DOWNLOADER/0.1";
-const DEFAULT_CONFIG_LOCATION: &'static str = "config.json";
-const DEFAULT_STATE_LOCATION: &'static str = "state.json";
-const DEFAULT_CLIP_INFO_LOCATION: &'static str = "clip_info/";
-const DEFAULT_DOWNLOAD_LOCATION: &'static str = "clips/";
-
-fn create_client_with_headers() -> Client {
-    info!("Creating Client.");
-    Client::builder()
-        .user_agent(USER_AGENT)
-        .build()
-        .expect("Could not build client")
-}
-
-fn create_request_auth_headers(config: &Config, state: &State) -> HeaderMap {
-    info!("Creating request Headers.");
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert(
-        reqwest::header::AUTHORIZATION,
-        HeaderValue::from_str(&format!(
-            "Bearer {}",
-            state
-                .auth_token
-                .clone()
-                .expect("Could not get access token for requests")
-        ))
-        .expect("Could not create auth header value"),
-    );
-    headers.insert(
-        "client-id",
-        HeaderValue::from_str(&config.client_id).expect("Could not insert client-id header value"),
-    );
-    headers
-}
-
-use twitch_api_rs::Config;
-
-pub async fn update_auth(
-    config: &Config,
-    state: &mut crate::state::State,
-    state_path: Option<PathBuf>,
-) {
-    trace!("Checking on auth status");
-    if let Some(auth_timeout) = state.auth_timeout {
-        let now = time::OffsetDateTime::now_utc();
-        if auth_timeout > now {
-            trace!("Reusing Auth Token");
-            let mut remaining = auth_timeout - now;
-            info!(
-                "Time before re-auth: Days: {} HMS: {}-{}-{}",
-                remaining.whole_days(),
-                {
-                    remaining -= remaining.whole_days().days();
-                    remaining.whole_hours()
-                },
-                {
-                    remaining -= remaining.whole_hours().hours();
-                    remaining.whole_minutes()
-                },
-                {
-                    remaining -= remaining.whole_minutes().minutes();
-                    remaining.whole_seconds()
-                }
-            );
-            return; // Return early if the timeout has not yet passed
-        }
-    }
-
-    info!("Getting new Auth token for account");
-    let auth_request: AuthRequest = AuthRequestBuilder::default()
-        .client_id(config.client_id.clone())
-        .client_secret(config.client_secret.clone())
-        .build()
-        .expect("Could not create auth request");
-
-    let auth_response: AuthResponse = match auth_request.make_request(&Client::new()).await {
-        Some(PossibleAuthResponse::AuthResponse(auth_response)) => auth_response,
-        Some(PossibleAuthResponse::BadRequest(bad_request)) => {
-            warn!("Bad Request: {:#?}", bad_request);
-            std::process::exit(-1);
-        }
-        None => {
-            warn!("Could not complete request");
-            std::process::exit(-1);
-        }
-    };
-
-    info!("Recieved Auth Response:\n{:#?}", &auth_response);
-    state.auth_token.replace(auth_response.access_token);
-    state
-        .auth_timeout
-        .replace(time::OffsetDateTime::now_utc() + auth_response.expires_in.seconds());
-
-    info!("Auth changed, writing into state");
-    crate::state::save(state, state_path);
-}
-
-pub async fn get_clip_info(
-    _user: String,
-    client: &Client,
-    spinner_style: indicatif::ProgressStyle,
-    headers: HeaderMap,
-) -> clip_download::Clips {
-    let bar = indicatif::ProgressBar::new_spinner().with_style(spinner_style);
-    bar.set_message("Retrieving Clips");
-    bar.enable_steady_tick(50);
-
-    let ret =
-        clip_download::get_all_clip_info(String::from("nikeairjordan9"), &client, headers).await;
-    if let Some(inner) = ret {
-        bar.finish_with_message(&format!("Finished with {} items", inner.clips.len()));
-        inner
-    } else {
-        bar.finish_with_message("Failed to return data");
-        error!("Could not get clips data");
-        std::process::exit(-1);
-    }
-}
-
-fn create_download_links(
-    mut clips: clip_download::Clips,
-    bar_style: indicatif::ProgressStyle,
-) -> clip_download::Clips {
-    let regex = Regex::new(r"(?m)(-preview-\d+x\d+\.[a-zA-Z]+)").expect("Could not create regex");
-    let replace = r".mp4";
-
-    let bar = indicatif::ProgressBar::new(clips.clips.len() as u64).with_style(bar_style);
-    bar.set_message("Creating download urls");
-    bar.enable_steady_tick(50);
-
-    (clips.clips)
-        .par_iter_mut()
-        .for_each(|clip: &mut clip_download::ClipInfo| {
-            clip.video_url = Some(regex.replace(&clip.thumbnail_url, replace).to_string());
-            bar.inc(1);
-        });
-
-    bar.finish_with_message("Finished creating video urls");
-    clips
-}
-
-async fn download_clips(
-    client: Client,
-    clips: clip_download::Clips,
-    user: &String,
-    location: Option<PathBuf>,
-    bar_style: indicatif::ProgressStyle,
-) {
-    let location = if let Some(loc) = location {
-        loc
-    } else {
-        let mut loc = PathBuf::from(DEFAULT_DOWNLOAD_LOCATION);
-        loc.push(user);
-        loc
-    };
-
-    std::fs::DirBuilder::new()
-        .recursive(true)
-        .create(&location)
-        .expect("Could not create download dir");
-
-    let bar = indicatif::ProgressBar::new(clips.clips.len() as u64).with_style(bar_style);
-    bar.set_message("Dowloading Clips");
-    bar.tick();
-    bar.enable_steady_tick(50);
-
-    let regex = Regex::new(r"-offset-(\d+)").expect("Could not compile regex");
-    let osify = Regex::new(r"(?m)(/)").expect("Could not build regex");
-
-    let mut infos = Vec::with_capacity(clips.clips.len());
-    let mut returns = Vec::with_capacity(clips.clips.len());
-    ((clips.clips)
-        .into_par_iter()
-        .map(|clip: clip_download::ClipInfo| {
-            if let Some(clip_url) = clip.video_url {
-                let mut loc = location.clone();
-                loc.push(&format!(
-                    "{}({}) {}.mp4",
-                    &clip.created_date,
-                    match regex.captures(&clip_url) {
-                        Some(caps) => {
-                            if let Some(cap) = caps.get(1) {
-                                cap.as_str()
-                            } else {
-                                "0"
-                            }
-                        }
-                        None => "0",
-                    },
-                    &osify.replace_all(&clip.name, r"-"),
-                ));
-                Some((clip_url, loc))
-            } else {
-                None
-            }
-        })
-        .collect_into_vec(&mut infos));
-
-    loop {
-        // Had to switch to this method (only 10 * 2 file descriptors open at a time) because requests were timing out
-        let mut join_handles = Vec::with_capacity(10);
-        // Take the first 10 (or remaining if less), and set aside the remaining (or none if less)
-        let new_inner = if infos.len() > 10 {
-            infos.split_off(10)
-        } else if infos.len() > 0 {
-            Vec::new()
-        } else {
-            break;
-        };
-
-        // spawn a task for each
-        for info in infos {
-            if let Some((clip_url, clip_loc)) = info {
-                join_handles.push(tokio::task::spawn(clip_download::download_clip(
-                    client.clone(),
-                    clip_url,
-                    clip_loc,
-                    bar.clone(),
-                )));
-            }
-        }
-
-        // Wait for each to finish
-        for handle in join_handles.into_iter() {
-            returns.push(handle.await);
-        }
-
-        infos = new_inner;
-    }
-
-    for ret in returns {
-        if let Err(e) = ret {
-            eprintln!("Could not download clip for reason: {}", e);
-        }
-    }
-
-    bar.finish_with_message("Downloaded all clips");
-}
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() {
@@ -265,202 +24,467 @@ async fn main() {
     let args = args::Args::from_args();
     debug!("Parsed Args:\n{:#?}", &args);
 
+    if let Err(e) = run(args).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+    trace!("Finished");
+}
+
+async fn run(args: args::Args) -> Result<()> {
     // Get configuration, exits application if initalized to default
     let config = match config::get_config(args.config.clone()) {
-        Some(cfg) => cfg,
-        None => {
-            config::write_default(args.config);
-            std::process::exit(-1);
+        Ok(cfg) => cfg,
+        Err(Error::NotFound(what)) => {
+            let location = config::write_default(args.config)?;
+            println!(
+                "Created Basic Config file at {:?}, please fill in information",
+                &location
+            );
+            return Err(Error::NotFound(what));
         }
+        Err(e) => return Err(e),
     };
 
-    let mut state = match state::load(args.state.clone()) {
-        Some(state) => state,
-        None => state::State::default(),
-    };
-
-    let client = create_client_with_headers();
+    let state = state::load(args.state.clone())?.unwrap_or_default();
 
-    let spinner_style = indicatif::ProgressStyle::default_spinner()
-        .tick_chars("/|\\—")
-        .template("| {spinner} | {wide_msg:.cyan} |");
-    let bar_style = indicatif::ProgressStyle::default_bar()
-        .progress_chars("=> ")
-        .tick_chars("/|\\—")
-        .template(
-            "| {spinner} | {msg:40.cyan/white} [{wide_bar:.green}] [ Eta:{eta:4} | {pos:>4}/{len:4} ] |",
-        );
+    let downloader = ClipDownloader::new(config.clone(), state, args.state.clone())?
+        .with_progress(Arc::new(bars::Bars::new()))
+        .with_quality(args.quality);
 
     use args::Commands::*;
     match args.command {
         CheckAuth => {
             info!("Subcommand Auth");
             // Get the current auth token or if outdated then get a new one
-            update_auth(&config, &mut state, args.state.clone()).await;
+            downloader.authenticate().await?;
         }
-        ClipInfo { user, clips } => {
+        ClipInfo {
+            user,
+            game,
+            clips,
+            listing,
+        } => {
             info!("Subcommand Get Clip Info");
+            let listing = clip_download::ListingOptions::from(listing);
             // Get the current auth token or if outdated then get a new one
-            update_auth(&config, &mut state, args.state.clone()).await;
-
-            state::save(&state, args.state.clone());
-
-            let request_auth_headers = create_request_auth_headers(&config, &state);
+            downloader.authenticate().await?;
 
-            let resp = get_clip_info(
-                user.clone(),
-                &client,
-                spinner_style.clone(),
-                request_auth_headers.clone(),
-            )
-            .await;
-
-            // Save to file
-            let path = if let Some(path) = clips {
-                path
-            } else {
-                // Ensure default path exists
-                std::fs::DirBuilder::new()
-                    .recursive(true)
-                    .create(DEFAULT_CLIP_INFO_LOCATION)
-                    .expect("Could not create default dir");
-
-                let mut path = PathBuf::from(DEFAULT_CLIP_INFO_LOCATION);
-                path.push(user);
-                path.with_extension("json")
+            let (resp, key) = match (user, game) {
+                (Some(user), _) => {
+                    let broadcaster = downloader.resolve_broadcaster(&user).await?;
+                    let resp = downloader.list_clips(&broadcaster, &listing).await?;
+                    (resp, broadcaster.login)
+                }
+                (None, Some(game)) => {
+                    let game = downloader
+                        .resolve_games(&[game])
+                        .await?
+                        .pop()
+                        .expect("Invariant failed");
+                    let resp = downloader.list_game_clips(&game, &listing).await?;
+                    (resp, game.key())
+                }
+                (None, None) => {
+                    return Err(Error::NotFound(String::from(
+                        "clips to list, provide either a user or a game",
+                    )));
+                }
             };
 
-            if let Ok(file) = std::fs::File::create(&path) {
-                let writer = std::io::BufWriter::new(file);
-                serde_json::to_writer_pretty(writer, &resp).expect("Could not serialize resp");
-            } else {
-                error!(
-                    "Could not write to {}",
-                    &path.to_str().expect("invalid path string")
-                );
-            }
+            // Save to file
+            let path = clips.unwrap_or_else(|| clip_info_path(&key));
+            write_clip_info(&path, &resp)?;
         }
-        DownloadLinks { user, clips } => {
+        DownloadLinks {
+            user,
+            clips,
+            selection,
+        } => {
+            // File locations are named after the login, however the user was given
             let path = if let Some(path) = clips {
                 path
             } else if let Some(ref user) = user {
-                let mut path = PathBuf::from(DEFAULT_CLIP_INFO_LOCATION);
-                path.push(user);
-                path.with_extension("json")
+                clip_info_path(&downloader.broadcaster_login(user).await?)
             } else {
-                error!("Must provide either path or user");
-                std::process::exit(-1);
+                return Err(Error::NotFound(String::from(
+                    "clip info file, provide either a path or a user",
+                )));
             };
 
-            let clips = if let Ok(file) = std::fs::File::open(&path) {
-                let reader = std::io::BufReader::new(file);
-                if let Ok(clips) = serde_json::from_reader::<_, clip_download::Clips>(reader) {
-                    clips
-                } else {
-                    error!("Clip Info File was not able to be parsed");
-                    std::process::exit(-1);
-                }
+            let clips = if let Some(clips) = read_clip_info(&path)? {
+                clips
+            } else if let Some(ref user) = user {
+                downloader.authenticate().await?;
+                let broadcaster = downloader.resolve_broadcaster(user).await?;
+                downloader
+                    .list_clips(&broadcaster, &clip_download::ListingOptions::default())
+                    .await?
             } else {
-                if let Some(ref user) = user {
-                    update_auth(&config, &mut state, args.state.clone()).await;
-
-                    state::save(&state, args.state.clone());
+                return Err(Error::NotFound(format!(
+                    "clip info file {:?} and user not provided",
+                    &path
+                )));
+            };
 
-                    let request_auth_headers = create_request_auth_headers(&config, &state);
+            let mut clips = clips;
+            if let Some(sort) = selection.sort {
+                clips.sort(sort);
+            }
 
-                    get_clip_info(
-                        user.clone(),
-                        &client,
-                        spinner_style.clone(),
-                        request_auth_headers,
-                    )
-                    .await
-                } else {
-                    error!(
-                        "Clip Info File does not exist and user not provided: {:?}",
-                        &path
-                    );
-                    std::process::exit(-1);
-                }
+            // create downlaod links, only for the selected clips but keeping all in the file
+            let rest = match selection.limit {
+                Some(limit) if limit < clips.clips.len() => clips.clips.split_off(limit),
+                _ => Vec::new(),
             };
+            let mut new_clips = downloader.create_download_links(clips).await;
+            new_clips.clips.extend(rest);
 
-            // create downlaod links
-            let new_clips = create_download_links(clips, bar_style.clone());
-
-            if let Ok(file) = std::fs::File::create(&path) {
-                let writer = std::io::BufWriter::new(file);
-                serde_json::to_writer_pretty(writer, &new_clips).expect("Could not serialize resp");
-            } else {
-                error!(
-                    "Could not write to {}",
-                    &path.to_str().expect("invalid path string")
-                );
-            }
+            write_clip_info(&path, &new_clips)?;
         }
-        DownloadClips { user, clips } => {
+        DownloadClips {
+            user,
+            clips,
+            listing,
+            filter,
+            selection,
+            jobs,
+            name_template,
+        } => {
             info!("Subcommand Download Clips");
+            let listing = clip_download::ListingOptions::from(listing);
+            let namer: naming::NameTemplate = name_template
+                .as_ref()
+                .unwrap_or(&config.name_template)
+                .parse()?;
+
+            // Games can be given by name, clips only record their id
+            let mut filter = filter::ClipFilter::from(filter);
+            let games = if filter.games.is_empty() {
+                Vec::new()
+            } else {
+                downloader.authenticate().await?;
+                downloader.resolve_games(&filter.games).await?
+            };
+            filter.games = games.iter().map(|game| game.id.clone()).collect();
+
+            // Without a user a single game is listed across all broadcasters
+            let game = match (&user, games.as_slice()) {
+                (None, [game]) => Some(game.clone()),
+                _ => None,
+            };
+            let key = match (&user, &game) {
+                (Some(user), _) => Some(downloader.broadcaster_login(user).await?),
+                (None, Some(game)) => Some(game.key()),
+                (None, None) => None,
+            };
+
             let path = if let Some(path) = clips {
                 path
-            } else if let Some(ref user) = user {
-                let mut path = PathBuf::from(DEFAULT_CLIP_INFO_LOCATION);
-                path.push(user);
-                path.with_extension("json")
+            } else if let Some(ref key) = key {
+                clip_info_path(key)
             } else {
-                error!("Must provide either path or user");
-                std::process::exit(-1);
+                return Err(Error::NotFound(String::from(
+                    "clip info file, provide either a path, a user or a single game",
+                )));
             };
 
-            let mut clips = if let Ok(file) = std::fs::File::open(&path) {
-                let reader = std::io::BufReader::new(file);
-                if let Ok(clips) = serde_json::from_reader::<_, clip_download::Clips>(reader) {
-                    clips
-                } else {
-                    error!("Clip Info File was not able to be parsed");
-                    std::process::exit(-1);
+            let mut clips = if let Some(clips) = read_clip_info(&path)? {
+                // The file is not listed again, a date range still restricts what is downloaded
+                if listing.windowed {
+                    warn!("{:?} is already listed, --windowed has no effect", &path);
                 }
+                filter.since = filter.since.max(listing.started_at);
+                filter.until = match (filter.until, listing.ended_at) {
+                    (Some(until), Some(ended_at)) => Some(until.min(ended_at)),
+                    (until, ended_at) => until.or(ended_at),
+                };
+                clips
+            } else if let Some(ref user) = user {
+                downloader.authenticate().await?;
+                let broadcaster = downloader.resolve_broadcaster(user).await?;
+                downloader.list_clips(&broadcaster, &listing).await?
+            } else if let Some(ref game) = game {
+                let clips = downloader.list_game_clips(game, &listing).await?;
+
+                // Listing a whole game takes a while, keep it for the next run
+                write_clip_info(&path, &clips)?;
+                clips
             } else {
-                if let Some(ref user) = user {
-                    update_auth(&config, &mut state, args.state.clone()).await;
-
-                    state::save(&state, args.state.clone());
+                return Err(Error::NotFound(format!(
+                    "clip info file {:?} and user not provided",
+                    &path
+                )));
+            };
 
-                    let request_auth_headers = create_request_auth_headers(&config, &state);
+            // The order is kept in the file, as with download-links
+            if let Some(sort) = selection.sort {
+                clips.sort(sort);
+            }
 
-                    get_clip_info(
-                        user.clone(),
-                        &client,
-                        spinner_style.clone(),
-                        request_auth_headers,
-                    )
-                    .await
-                } else {
-                    error!(
-                        "Clip Info File does not exist and user not provided: {:?}",
-                        &path
-                    );
-                    std::process::exit(-1);
-                }
-            };
+            // Everything stays in the file so names are worked out over all clips, only the
+            // selection is downloaded
+            let mut selected: Vec<&clip_download::ClipInfo> = (clips.clips)
+                .iter()
+                .filter(|clip| filter.matches(clip))
+                .collect();
+            if !filter.is_empty() {
+                info!(
+                    "{} of {} clips match the filter",
+                    selected.len(),
+                    clips.clips.len()
+                );
+            }
+            if let Some(limit) = selection.limit {
+                selected.truncate(limit);
+            }
+            let selected: HashSet<String> = selected
+                .into_iter()
+                .map(|clip| clip.key().to_string())
+                .collect();
+            let is_selected = |clip: &clip_download::ClipInfo| selected.contains(clip.key());
 
             if (clips.clips)
                 .par_iter()
-                .map(|clip: &clip_download::ClipInfo| clip.video_url.is_none())
-                .reduce(|| true, |a, b| a & b)
+                .filter(|&clip| is_selected(clip))
+                .all(|clip| clip.video_url.is_none())
             {
                 info!("No clip contained dowload info so attempting to create download links");
-                clips = create_download_links(clips, bar_style.clone());
+                clips = downloader.resolve_video_urls(clips, &is_selected).await;
             }
 
             info!("Downloading clips");
-            download_clips(
-                client.clone(),
-                clips,
-                &user.unwrap_or(String::from("empty")),
-                None,
-                bar_style.clone(),
-            )
-            .await;
+            let failures = downloader
+                .download_and_record(
+                    &mut clips,
+                    &is_selected,
+                    &download_location(&key.unwrap_or(String::from("empty"))),
+                    &namer,
+                    jobs.unwrap_or(config.jobs),
+                )
+                .await?;
+            write_clip_info(&path, &clips)?;
+
+            for (clip, reason) in failures {
+                eprintln!("Could not download clip {} for reason: {}", clip, reason);
+            }
+        }
+        Sync {
+            user,
+            clips,
+            listing,
+            jobs,
+            name_template,
+        } => {
+            info!("Subcommand Sync");
+            let listing = clip_download::ListingOptions::from(listing);
+            let namer: naming::NameTemplate = name_template
+                .as_ref()
+                .unwrap_or(&config.name_template)
+                .parse()?;
+
+            downloader.authenticate().await?;
+
+            let broadcaster = downloader.resolve_broadcaster(&user).await?;
+            let path = clips.unwrap_or_else(|| clip_info_path(&broadcaster.login));
+
+            let listed = downloader.list_clips(&broadcaster, &listing).await?;
+
+            let report = downloader
+                .sync(
+                    listed,
+                    &listing,
+                    &path,
+                    &download_location(&broadcaster.login),
+                    &filter::ClipFilter::default(),
+                    &namer,
+                    jobs.unwrap_or(config.jobs),
+                )
+                .await?;
+
+            println!("Synced {}: {}", &broadcaster.login, report);
+        }
+        RunAll {
+            watchlist,
+            parallel,
+            jobs,
+        } => {
+            info!("Subcommand Run All");
+            let watchlist = watchlist::get_watchlist(watchlist)?;
+            let mut channels = watchlist.queue(&config.name_template)?;
+            let count = channels.len();
+
+            // Every channel is synced with the same token
+            downloader.authenticate().await?;
+
+            downloader.resolve_channel_games(&mut channels).await?;
+
+            let results = downloader
+                .sync_channels(channels, false, parallel, jobs.unwrap_or(config.jobs))
+                .await;
+
+            let mut total = SyncReport::default();
+            let mut errors = Vec::new();
+            for (_, user, ret) in results {
+                match ret {
+                    Ok(report) => {
+                        println!("Synced {}: {}", user, report);
+                        total.add(&report);
+                    }
+                    Err(e) => {
+                        eprintln!("Could not sync {}: {}", user, e);
+                        errors.push(e);
+                    }
+                }
+            }
+            println!(
+                "Synced {} of {} channels: {}",
+                count - errors.len(),
+                count,
+                total
+            );
+
+            // Exit with the code of the first failure
+            if let Some(e) = errors.into_iter().next() {
+                return Err(e);
+            }
+        }
+        Watch {
+            watchlist,
+            interval,
+            parallel,
+            jobs,
+        } => {
+            info!("Subcommand Watch");
+            let watchlist = watchlist::get_watchlist(watchlist)?;
+            let mut channels = watchlist.queue(&config.name_template)?;
+
+            // Problems with the credentials or watchlist should show up right away, anything after
+            // this is retried at the next poll
+            downloader.authenticate().await?;
+
+            downloader.resolve_channel_games(&mut channels).await?;
+
+            let interval = std::time::Duration::from_secs(std::cmp::max(1, interval));
+            loop {
+                // Renews the token ahead of its expiry, tokens that are rejected anyway are renewed
+                // when a request fails
+                match downloader.authenticate().await {
+                    Ok(()) => {
+                        let results = downloader
+                            .sync_channels(
+                                channels.clone(),
+                                true,
+                                parallel,
+                                jobs.unwrap_or(config.jobs),
+                            )
+                            .await;
+
+                        for (_, user, ret) in results {
+                            match ret {
+                                Ok(report) if report.merge.added > 0 || report.failed > 0 => {
+                                    println!(
+                                        "Polled {}: {} new, {} downloaded, {} failed",
+                                        user, report.merge.added, report.downloaded, report.failed
+                                    );
+                                }
+                                Ok(_) => debug!("Polled {}: nothing new", user),
+                                Err(e) => {
+                                    eprintln!("Could not poll {}, retrying next poll: {}", user, e)
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("Could not refresh auth, retrying next poll: {}", e),
+                }
+
+                info!("Next poll in {} seconds", interval.as_secs());
+                tokio::time::delay_for(interval).await;
+            }
+        }
+        Clip {
+            clips,
+            from_file,
+            jobs,
+            name_template,
+        } => {
+            info!("Subcommand Clip");
+            let namer: naming::NameTemplate = name_template
+                .as_ref()
+                .unwrap_or(&config.name_template)
+                .parse()?;
+
+            let mut ids = clips;
+            if let Some(from_file) = from_file {
+                let contents =
+                    std::fs::read_to_string(&from_file).map_err(Error::io(&from_file))?;
+                for (number, line) in contents.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let id = clip_download::parse_clip_id(line).map_err(|e| {
+                        Error::Parse(format!("{:?} line {}: {}", &from_file, number + 1, e))
+                    })?;
+                    ids.push(id);
+                }
+            }
+            let mut seen = HashSet::new();
+            ids.retain(|id| seen.insert(id.clone()));
+            if ids.is_empty() {
+                return Err(Error::NotFound(String::from(
+                    "clips to download, give urls or ids or --from-file",
+                )));
+            }
+
+            downloader.authenticate().await?;
+
+            let clips = downloader.get_clips(&ids).await?;
+
+            // Clips are saved with the rest of their broadcaster's clips
+            let mut by_broadcaster: HashMap<String, clip_download::Clips> = HashMap::new();
+            for clip in clips.clips {
+                by_broadcaster
+                    .entry(clip.broadcaster_id.clone())
+                    .or_default()
+                    .clips
+                    .push(clip);
+            }
+
+            for (broadcaster_id, clips) in by_broadcaster {
+                let broadcaster = downloader
+                    .resolve_broadcaster(&BroadcasterRef::Id(broadcaster_id))
+                    .await?;
+
+                // Names are worked out among all the broadcaster's known clips, so a clip that
+                // already has a name keeps it
+                let path = clip_info_path(&broadcaster.login);
+                let mut known = read_clip_info(&path)?.unwrap_or_default();
+                let requested: HashSet<String> = clips
+                    .clips
+                    .iter()
+                    .map(|clip| clip.key().to_string())
+                    .collect();
+                known.merge(clips, &clip_download::ListingOptions::default());
+                let is_requested = |clip: &clip_download::ClipInfo| requested.contains(clip.key());
+
+                let mut known = downloader.resolve_video_urls(known, &is_requested).await;
+                let failures = downloader
+                    .download_and_record(
+                        &mut known,
+                        &is_requested,
+                        &download_location(&broadcaster.login),
+                        &namer,
+                        jobs.unwrap_or(config.jobs),
+                    )
+                    .await?;
+                write_clip_info(&path, &known)?;
+
+                for (clip, reason) in failures {
+                    eprintln!("Could not download clip {} for reason: {}", clip, reason);
+                }
+            }
         }
     }
-    trace!("Finished");
+    Ok(())
 }
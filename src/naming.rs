@@ -0,0 +1,325 @@
+use crate::clip_download::ClipInfo;
+use crate::error::{Error, Result};
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Names clips as they were named before templates, `<date>(<vod offset>) <title>.mp4`, except
+/// that characters windows does not allow, such as the `:`s of the date, become `-`. Clips saved
+/// under the old names are still found, see `NameTemplate::legacy_path`.
+pub const DEFAULT_NAME_TEMPLATE: &'static str = "{date}({vod_offset}) {title}.mp4";
+
+/// Longest file or directory name produced, in bytes. Most filesystems allow 255, this leaves
+/// room for the part file extension and a collision suffix.
+const MAX_COMPONENT_BYTES: usize = 200;
+
+/// Names windows refuses to create files with, regardless of extension
+const RESERVED_NAMES: [&'static str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Make a single file or directory name safe to use on windows, smb shares and unix alike.
+///
+/// Characters that are invalid anywhere (`<>:"/\|?*` and control characters) become `-`,
+/// trailing dots and spaces are dropped, reserved device names get a `_` appended, and the name
+/// is cut to `MAX_COMPONENT_BYTES` (keeping a short extension) without splitting a character.
+pub fn sanitize(component: &str) -> String {
+    let replaced: String = component
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect();
+
+    // Keep the extension intact when the name has to be shortened
+    let (stem, extension) = match replaced.rfind('.') {
+        Some(dot) if dot > 0 && replaced.len() - dot <= 10 => replaced.split_at(dot),
+        _ => (replaced.as_str(), ""),
+    };
+    let stem = truncate(stem, MAX_COMPONENT_BYTES.saturating_sub(extension.len()));
+    let mut name = format!("{}{}", stem, extension);
+
+    let trimmed = name.trim_end_matches(|c| c == '.' || c == ' ').len();
+    name.truncate(trimmed);
+    let trimmed = name.len() - name.trim_start_matches(' ').len();
+    name.replace_range(..trimmed, "");
+
+    // 'con.mp4' is as reserved as 'con'
+    let base = name.split('.').next().unwrap_or("").trim_end();
+    let reserved = RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base));
+    if reserved {
+        let base_len = base.len();
+        name.insert(base_len, '_');
+    }
+
+    if name.is_empty() {
+        String::from("_")
+    } else {
+        name
+    }
+}
+
+/// The longest prefix of `s` that fits in `max` bytes
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Insert `suffix` before the extension of the file `path` points to
+fn with_suffix(path: &PathBuf, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// A value that can be substituted into a name template
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Id,
+    Title,
+    Creator,
+    Broadcaster,
+    Game,
+    Date,
+    Views,
+    Duration,
+    VodOffset,
+}
+
+impl FromStr for Field {
+    type Err = ();
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match name {
+            "id" => Field::Id,
+            "title" => Field::Title,
+            "creator" => Field::Creator,
+            "broadcaster" => Field::Broadcaster,
+            "game" => Field::Game,
+            "date" => Field::Date,
+            "views" => Field::Views,
+            "duration" => Field::Duration,
+            "vod_offset" => Field::VodOffset,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// A field, with the format given after a `:` for dates
+    Field(Field, Option<String>),
+}
+
+/// Template for the path (relative to the download directory) a clip is saved to.
+///
+/// Placeholders are written `{name}`, a literal brace as `{{` or `}}`, and `/` starts a
+/// subdirectory. Available placeholders are `{id}`, `{title}`, `{creator}`, `{broadcaster}`,
+/// `{game}` (the numeric game id, clip listings do not include the game name), `{views}`,
+/// `{duration}` (whole seconds), `{vod_offset}` (seconds into the vod, `0` if unknown) and
+/// `{date}`, which is the creation timestamp as given by twitch unless a format is given, as in
+/// `{date:%Y-%m-%d}`.
+#[derive(Debug, Clone)]
+pub struct NameTemplate {
+    segments: Vec<Segment>,
+    offset: Regex,
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        DEFAULT_NAME_TEMPLATE.parse().expect("Invariant failed")
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let invalid =
+            |reason: &str| Error::Parse(format!("name template {:?}: {}", template, reason));
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(invalid("unclosed '{'")),
+                        }
+                    }
+
+                    let mut parts = placeholder.splitn(2, ':');
+                    let name = parts.next().unwrap_or("").trim();
+                    let format = parts.next().map(String::from);
+                    let field: Field = name
+                        .parse()
+                        .map_err(|_| invalid(&format!("unknown placeholder {{{}}}", name)))?;
+                    if format.is_some() && field != Field::Date {
+                        return Err(invalid(&format!("{{{}}} does not take a format", name)));
+                    }
+                    if let Some(format) = format.as_deref() {
+                        time::OffsetDateTime::unix_epoch()
+                            .try_format(format)
+                            .map_err(|e| invalid(&format!("date format {:?}: {}", format, e)))?;
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(field, format));
+                }
+                '}' => return Err(invalid("unmatched '}'")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        if segments.is_empty() {
+            return Err(invalid("template is empty"));
+        }
+
+        Ok(Self {
+            segments,
+            offset: Regex::new(r"-offset-(\d+)").expect("Could not compile regex"),
+        })
+    }
+}
+
+impl NameTemplate {
+    /// Location of the downloaded clip, or none if it does not have a download url yet
+    pub fn path(&self, location: &PathBuf, clip: &ClipInfo) -> Option<PathBuf> {
+        let clip_url = clip.video_url.as_ref()?;
+
+        let mut name = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(literal) => name.push_str(literal),
+                Segment::Field(field, format) => {
+                    let value = self.value(clip, clip_url, *field, format.as_deref());
+                    // Values must not be able to create directories of their own
+                    name.push_str(&value.replace('/', "-"));
+                }
+            }
+        }
+
+        let mut loc = location.clone();
+        for component in name.split('/').filter(|component| !component.is_empty()) {
+            loc.push(sanitize(component));
+        }
+        Some(loc)
+    }
+
+    /// Locations of a set of clips, see `path`.
+    ///
+    /// Clips saved in `location` before keep the name they were saved under (`saved_path`), and
+    /// no other clip is given that name. Other clips that would end up at the same location
+    /// (ignoring case, as windows does) are told apart by appending ` [<clip id>]` to the name of
+    /// all but the oldest. Pass every clip of the ClipInfo file, not just those to download, so
+    /// names do not depend on which clips were picked.
+    pub fn paths(&self, location: &PathBuf, clips: &[ClipInfo]) -> Vec<Option<PathBuf>> {
+        let mut paths: Vec<Option<PathBuf>> = clips
+            .iter()
+            .map(|clip| {
+                clip.saved_path
+                    .clone()
+                    .filter(|saved| saved.starts_with(location))
+            })
+            .collect();
+
+        let fold = |path: &PathBuf| path.to_string_lossy().to_lowercase();
+        let mut taken: HashSet<String> = paths.iter().flatten().map(fold).collect();
+
+        let mut order: Vec<usize> = (0..clips.len()).filter(|&i| paths[i].is_none()).collect();
+        order.sort_by(|&a, &b| {
+            (&clips[a].created_date, clips[a].key()).cmp(&(&clips[b].created_date, clips[b].key()))
+        });
+
+        for i in order {
+            let path = match self.path(location, &clips[i]) {
+                Some(path) => path,
+                None => continue,
+            };
+            let path = if taken.insert(fold(&path)) {
+                path
+            } else {
+                let path = with_suffix(&path, &format!(" [{}]", sanitize(clips[i].key())));
+                taken.insert(fold(&path));
+                path
+            };
+            paths[i] = Some(path);
+        }
+        paths
+    }
+
+    /// Location the clip was saved to before name templates, when names were not sanitized
+    pub fn legacy_path(&self, location: &PathBuf, clip: &ClipInfo) -> Option<PathBuf> {
+        let clip_url = clip.video_url.as_ref()?;
+        let name = format!(
+            "{}({}) {}.mp4",
+            &clip.created_date,
+            self.value(clip, clip_url, Field::VodOffset, None),
+            clip.name.replace('/', "-")
+        );
+        Some(location.join(name))
+    }
+
+    fn value(&self, clip: &ClipInfo, clip_url: &str, field: Field, format: Option<&str>) -> String {
+        match field {
+            Field::Id => clip.id.clone(),
+            Field::Title => clip.name.clone(),
+            Field::Creator => clip.created_by.clone(),
+            Field::Broadcaster => clip.broadcaster_name.clone(),
+            // Clips only record the game id
+            Field::Game => clip.game_id.clone(),
+            Field::Date => match (format, crate::timestamp::parse(&clip.created_date)) {
+                (Some(format), Ok(date)) => date
+                    .try_format(format)
+                    .unwrap_or_else(|_| clip.created_date.clone()),
+                _ => clip.created_date.clone(),
+            },
+            Field::Views => clip.view_count.to_string(),
+            Field::Duration => format!("{:.0}", clip.duration),
+            Field::VodOffset => self
+                .offset
+                .captures(clip_url)
+                .and_then(|caps| caps.get(1))
+                .map(|cap| cap.as_str())
+                .unwrap_or("0")
+                .to_string(),
+        }
+    }
+}
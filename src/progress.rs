@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+/// Receives progress reports from long running work, so any interface (a terminal, a service's
+/// logs, nothing at all) can show it in its own way
+pub trait Progress: Send + Sync {
+    /// Start reporting a task, `len` is the number of steps it takes if known up front
+    fn task(&self, message: &str, len: Option<u64>) -> Arc<dyn Task>;
+}
+
+/// A single task reported through `Progress`. Tasks may be advanced from several threads at once.
+pub trait Task: Send + Sync {
+    /// Describe what the task is doing now
+    fn set_message(&self, _message: &str) {}
+
+    /// `steps` more steps of the task are done
+    fn advance(&self, _steps: u64) {}
+
+    /// The task ended, successfully or not
+    fn finish(&self, _message: &str) {}
+}
+
+/// Discards all progress reports
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Silent;
+
+impl Progress for Silent {
+    fn task(&self, _message: &str, _len: Option<u64>) -> Arc<dyn Task> {
+        Arc::new(Silent)
+    }
+}
+
+impl Task for Silent {}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// Resolution and frame rate of a clip rendition, such as 720p60
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quality {
+    pub height: u32,
+    /// Frames per second, none if unknown
+    pub fps: Option<u32>,
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fps {
+            Some(fps) => write!(f, "{}p{}", self.height, fps),
+            None => write!(f, "{}p", self.height),
+        }
+    }
+}
+
+impl std::str::FromStr for Quality {
+    type Err = &'static str;
+
+    /// `<height>`, `<height>p` or `<height>p<fps>`
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        const ERROR: &'static str = "Expected a quality like 1080, 720p or 720p60";
+        let input = input.trim().to_ascii_lowercase();
+        let (height, fps) = match input.find('p') {
+            Some(index) => (&input[..index], &input[index + 1..]),
+            None => (input.as_str(), ""),
+        };
+        let height = height.parse().map_err(|_| ERROR)?;
+        let fps = match fps {
+            "" => None,
+            fps => Some(fps.parse().map_err(|_| ERROR)?),
+        };
+        Ok(Quality { height, fps })
+    }
+}
+
+/// The rendition to download when a clip is available in several
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QualityPreference {
+    Best,
+    Worst,
+    /// This quality, or the nearest available. Without a frame rate the highest available at the
+    /// height is picked.
+    Exact(Quality),
+}
+
+impl Default for QualityPreference {
+    fn default() -> Self {
+        QualityPreference::Best
+    }
+}
+
+impl std::str::FromStr for QualityPreference {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "best" => Ok(QualityPreference::Best),
+            "worst" => Ok(QualityPreference::Worst),
+            _ => input
+                .parse()
+                .map(QualityPreference::Exact)
+                .map_err(|_| "Expected best, worst or a quality like 1080, 720p or 720p60"),
+        }
+    }
+}
+
+impl QualityPreference {
+    /// Index of the preferred quality in `available`. Unknown qualities (`None`) are only picked
+    /// when no known one is available. None if nothing is available.
+    pub fn select(&self, available: &[Option<Quality>]) -> Option<usize> {
+        let indices = 0..available.len();
+        match self {
+            QualityPreference::Best => indices.max_by_key(|&i| available[i]),
+            QualityPreference::Worst => {
+                indices.min_by_key(|&i| (available[i].is_none(), available[i]))
+            }
+            QualityPreference::Exact(wanted) => indices.min_by_key(|&i| match available[i] {
+                // Nearest height first, then nearest frame rate, preferring the higher of two
+                // equally near
+                Some(quality) => (
+                    false,
+                    distance(quality.height, wanted.height),
+                    std::cmp::Reverse(quality.height),
+                    match (quality.fps, wanted.fps) {
+                        (Some(fps), Some(wanted)) => distance(fps, wanted),
+                        _ => 0,
+                    },
+                    std::cmp::Reverse(quality.fps),
+                ),
+                None => (true, 0, std::cmp::Reverse(0), 0, std::cmp::Reverse(None)),
+            }),
+        }
+    }
+}
+
+fn distance(a: u32, b: u32) -> u32 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
@@ -0,0 +1,329 @@
+use crate::clip_download::ClipInfo;
+use crate::error::{Error, Result};
+use crate::quality::{Quality, QualityPreference};
+use crate::retry::{AttemptError, RetryPolicy};
+use async_trait::async_trait;
+use log::*;
+use regex::Regex;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Endpoint the twitch web player gets its clip playback urls from
+pub const DEFAULT_PLAYBACK_ENDPOINT: &'static str = "https://gql.twitch.tv/gql";
+
+/// Client id of the twitch web player, the playback endpoint does not accept other ids
+pub const DEFAULT_PLAYBACK_CLIENT_ID: &'static str = "kimne78kx3ncx6brgo4mv6wki5h1ko";
+
+/// Persisted query the web player uses to request a clip's playback access token
+pub const DEFAULT_PLAYBACK_QUERY_HASH: &'static str =
+    "36b89d2507fce29e5ca551df756d27c1cfe079e2609642b4390aa4c35796eb11";
+
+/// A version of a clip's video at one quality
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendition {
+    /// None if the resolver can not tell
+    pub quality: Option<Quality>,
+    pub url: String,
+}
+
+/// Works out the url a clip's video is downloaded from
+#[async_trait]
+pub trait VideoUrlResolver: Send + Sync {
+    /// Recorded on each clip whose video url this resolver produced
+    fn name(&self) -> &str;
+
+    /// The renditions `clip` is available in, empty if this resolver does not know how to find
+    /// them
+    async fn resolve(&self, clip: &ClipInfo) -> Result<Vec<Rendition>>;
+}
+
+/// Derives the video url from the thumbnail url, whose path the video shares up to
+/// `-preview-<width>x<height>.jpg`. Needs no requests but breaks whenever twitch changes its cdn
+/// layout, and only finds the source rendition whose quality it can not tell.
+pub struct ThumbnailResolver {
+    preview: Regex,
+}
+
+impl ThumbnailResolver {
+    pub fn new() -> Self {
+        Self {
+            preview: Regex::new(r"(?m)(-preview-\d+x\d+\.[a-zA-Z]+)")
+                .expect("Could not create regex"),
+        }
+    }
+}
+
+#[async_trait]
+impl VideoUrlResolver for ThumbnailResolver {
+    fn name(&self) -> &str {
+        "thumbnail"
+    }
+
+    async fn resolve(&self, clip: &ClipInfo) -> Result<Vec<Rendition>> {
+        if !self.preview.is_match(&clip.thumbnail_url) {
+            return Ok(Vec::new());
+        }
+        Ok(vec![Rendition {
+            quality: None,
+            url: self
+                .preview
+                .replace(&clip.thumbnail_url, ".mp4")
+                .to_string(),
+        }])
+    }
+}
+
+/// Asks a playback endpoint (by default the one the twitch web player uses) for the clip's
+/// renditions and signs their urls with the access token returned alongside them
+pub struct PlaybackResolver {
+    client: Client,
+    endpoint: String,
+    client_id: String,
+    query_hash: String,
+    retry: RetryPolicy,
+}
+
+impl PlaybackResolver {
+    pub fn new(
+        client: Client,
+        endpoint: String,
+        client_id: String,
+        query_hash: String,
+        retry: RetryPolicy,
+    ) -> Self {
+        Self {
+            client,
+            endpoint,
+            client_id,
+            query_hash,
+            retry,
+        }
+    }
+
+    async fn request(&self, clip: &ClipInfo) -> Result<PlaybackResponse> {
+        let body = json!({
+            "operationName": "VideoAccessToken_Clip",
+            "variables": { "slug": &clip.id },
+            "extensions": {
+                "persistedQuery": { "version": 1, "sha256Hash": &self.query_hash }
+            }
+        });
+
+        debug!(
+            "Requesting playback of clip {} from {}",
+            &clip.id, &self.endpoint
+        );
+        self.retry
+            .run("Playback", || self.attempt_request(&body))
+            .await
+    }
+
+    async fn attempt_request(
+        &self,
+        body: &serde_json::Value,
+    ) -> std::result::Result<PlaybackResponse, AttemptError> {
+        let res = self
+            .client
+            .post(&self.endpoint)
+            .header("client-id", &self.client_id)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| AttemptError::request(e, &self.retry))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            let error = Error::Api(format!("{} answered {}", &self.endpoint, status));
+            return Err(AttemptError::status(status, error, &self.retry));
+        }
+        res.json().await.map_err(|e| {
+            AttemptError::fatal(Error::Parse(format!(
+                "response from {}: {}",
+                &self.endpoint, e
+            )))
+        })
+    }
+}
+
+#[async_trait]
+impl VideoUrlResolver for PlaybackResolver {
+    fn name(&self) -> &str {
+        "playback"
+    }
+
+    async fn resolve(&self, clip: &ClipInfo) -> Result<Vec<Rendition>> {
+        // Clip info files written before ids were recorded only have the thumbnail url
+        if clip.id.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let clip = match self.request(clip).await?.data.clip {
+            Some(clip) => clip,
+            None => return Ok(Vec::new()),
+        };
+        let token = clip.playback_access_token;
+
+        let mut renditions = Vec::with_capacity(clip.video_qualities.len());
+        for video in clip.video_qualities {
+            let source = &video.source_url;
+            let url = Url::parse_with_params(
+                source,
+                &[("sig", &token.signature), ("token", &token.value)],
+            )
+            .map_err(|e| Error::Parse(format!("playback url {:?}: {}", source, e)))?;
+            renditions.push(Rendition {
+                quality: video.quality.parse().ok().map(|height| Quality {
+                    height,
+                    fps: video.frame_rate.map(|fps| fps.round() as u32),
+                }),
+                url: url.into_string(),
+            });
+        }
+        Ok(renditions)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PlaybackResponse {
+    data: PlaybackData,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlaybackData {
+    /// None if the clip does not exist (anymore)
+    clip: Option<PlaybackClip>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaybackClip {
+    playback_access_token: PlaybackAccessToken,
+    #[serde(default)]
+    video_qualities: Vec<VideoQuality>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlaybackAccessToken {
+    signature: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VideoQuality {
+    /// Height, such as "1080"
+    #[serde(default)]
+    quality: String,
+    #[serde(default, rename = "frameRate")]
+    frame_rate: Option<f64>,
+    #[serde(rename = "sourceURL")]
+    source_url: String,
+}
+
+/// A resolver as configured in the `resolvers` list of the config file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ResolverConfig {
+    /// See `ThumbnailResolver`
+    Thumbnail,
+    /// See `PlaybackResolver`
+    Playback {
+        #[serde(default = "default_playback_endpoint")]
+        endpoint: String,
+        #[serde(default = "default_playback_client_id")]
+        client_id: String,
+        #[serde(default = "default_playback_query_hash")]
+        query_hash: String,
+    },
+}
+
+fn default_playback_endpoint() -> String {
+    String::from(DEFAULT_PLAYBACK_ENDPOINT)
+}
+
+fn default_playback_client_id() -> String {
+    String::from(DEFAULT_PLAYBACK_CLIENT_ID)
+}
+
+fn default_playback_query_hash() -> String {
+    String::from(DEFAULT_PLAYBACK_QUERY_HASH)
+}
+
+/// Resolvers used when the config file does not list any. The playback endpoint is not part of
+/// the public api, so it is only asked when it is listed in the config.
+pub fn default_resolvers() -> Vec<ResolverConfig> {
+    vec![ResolverConfig::Thumbnail]
+}
+
+impl ResolverConfig {
+    pub fn build(&self, client: &Client, retry: &RetryPolicy) -> Box<dyn VideoUrlResolver> {
+        match self {
+            ResolverConfig::Thumbnail => Box::new(ThumbnailResolver::new()),
+            ResolverConfig::Playback {
+                endpoint,
+                client_id,
+                query_hash,
+            } => Box::new(PlaybackResolver::new(
+                client.clone(),
+                endpoint.clone(),
+                client_id.clone(),
+                query_hash.clone(),
+                retry.clone(),
+            )),
+        }
+    }
+}
+
+/// Resolvers tried in order until one finds a clip's video url
+pub struct ResolverChain {
+    resolvers: Vec<Box<dyn VideoUrlResolver>>,
+}
+
+impl ResolverChain {
+    pub fn new(resolvers: Vec<Box<dyn VideoUrlResolver>>) -> Self {
+        Self { resolvers }
+    }
+
+    pub fn from_config(configs: &[ResolverConfig], client: &Client, retry: &RetryPolicy) -> Self {
+        Self::new(
+            configs
+                .iter()
+                .map(|config| config.build(client, retry))
+                .collect(),
+        )
+    }
+
+    /// Set the video url of `clip` to the rendition `quality` prefers among those found by the
+    /// first resolver to find any, along with its quality and the resolver. If no resolver finds
+    /// one the clip is left as it is and false is returned.
+    pub async fn resolve(&self, clip: &mut ClipInfo, quality: &QualityPreference) -> bool {
+        for resolver in self.resolvers.iter() {
+            match resolver.resolve(clip).await {
+                Ok(mut renditions) if !renditions.is_empty() => {
+                    let qualities: Vec<_> = renditions.iter().map(|r| r.quality).collect();
+                    let index = quality.select(&qualities).expect("Invariant failed");
+                    let rendition = renditions.swap_remove(index);
+                    trace!(
+                        "{} resolved clip {} to {:?}",
+                        resolver.name(),
+                        clip.key(),
+                        &rendition
+                    );
+                    clip.video_url = Some(rendition.url);
+                    clip.video_url_resolver = Some(resolver.name().to_string());
+                    clip.video_quality = rendition.quality.map(|quality| quality.to_string());
+                    return true;
+                }
+                Ok(_) => debug!("{} could not resolve clip {}", resolver.name(), clip.key()),
+                Err(e) => warn!(
+                    "{} failed to resolve clip {}: {}",
+                    resolver.name(),
+                    clip.key(),
+                    e
+                ),
+            }
+        }
+        warn!("No resolver found a video url for clip {}", clip.key());
+        false
+    }
+}
@@ -0,0 +1,126 @@
+use crate::error::{Error, Result};
+use log::*;
+use rand::Rng;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+/// How often and how quickly failed requests are retried
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total attempts made before giving up, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each retry after that
+    pub base_delay_ms: u64,
+    /// Upper bound for the delay between attempts
+    pub max_delay_ms: u64,
+    /// Fraction of the delay that is randomized, between 0 and 1
+    pub jitter: f64,
+    /// Response statuses worth trying again
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+            jitter: 0.5,
+            retry_statuses: vec![429, 500, 502, 503, 504],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay to wait after the given (1 based) failed attempt
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay_ms
+            .saturating_mul(1u64 << exponent)
+            .min(self.max_delay_ms);
+
+        let jitter = self.jitter.max(0.0).min(1.0);
+        let scale = 1.0 - jitter * rand::thread_rng().gen::<f64>();
+        Duration::from_millis((delay as f64 * scale) as u64)
+    }
+
+    /// Whether there are attempts left after the given (1 based) attempt
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+
+    pub fn is_retryable_status(&self, status: StatusCode) -> bool {
+        self.retry_statuses.contains(&status.as_u16())
+    }
+
+    /// Timeouts, refused and reset connections, and bodies cut off midway are worth retrying,
+    /// anything else (bad urls, redirect loops, ...) will fail the same way again
+    pub fn is_retryable_error(&self, error: &reqwest::Error) -> bool {
+        if let Some(status) = error.status() {
+            return self.is_retryable_status(status);
+        }
+        error.is_timeout() || error.is_connect() || error.is_request() || error.is_body()
+    }
+
+    /// Wait out the delay after the given (1 based) failed attempt
+    pub async fn wait(&self, attempt: u32) {
+        tokio::time::delay_for(self.delay(attempt)).await;
+    }
+
+    /// Make attempts until one succeeds, one fails in a way that is not worth retrying, or there
+    /// are no attempts left, waiting between them. `what` names the attempts in log messages.
+    pub async fn run<T, F, Fut>(&self, what: &str, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = std::result::Result<T, AttemptError>>,
+    {
+        let mut number = 1;
+        loop {
+            match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(e) if e.retryable && self.should_retry(number) => {
+                    warn!("{} attempt {} failed, retrying: {}", what, number, &e.error);
+                    self.wait(number).await;
+                    number += 1;
+                }
+                Err(e) => return Err(e.error),
+            }
+        }
+    }
+}
+
+/// Why an attempt failed, and whether another attempt could succeed
+#[derive(Debug)]
+pub struct AttemptError {
+    pub retryable: bool,
+    pub error: Error,
+}
+
+impl AttemptError {
+    pub fn fatal(error: Error) -> Self {
+        Self {
+            retryable: false,
+            error,
+        }
+    }
+
+    /// A request that could not be completed
+    pub fn request(error: reqwest::Error, retry: &RetryPolicy) -> Self {
+        Self {
+            retryable: retry.is_retryable_error(&error),
+            error: error.into(),
+        }
+    }
+
+    /// A request answered with an error `status`
+    pub fn status(status: StatusCode, error: Error, retry: &RetryPolicy) -> Self {
+        Self {
+            retryable: retry.is_retryable_status(status),
+            error,
+        }
+    }
+}
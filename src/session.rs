@@ -0,0 +1,71 @@
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::state::State;
+use log::*;
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use std::future::Future;
+use std::path::PathBuf;
+
+/// What authenticated requests need: the headers to send, and everything needed to renew the
+/// token when twitch rejects it partway through a run
+pub struct Session<'a> {
+    pub config: &'a Config,
+    pub state: &'a mut State,
+    pub state_path: Option<PathBuf>,
+    pub headers: HeaderMap,
+}
+
+impl<'a> Session<'a> {
+    /// Start a session with the token currently in the state
+    pub fn new(
+        config: &'a Config,
+        state: &'a mut State,
+        state_path: Option<PathBuf>,
+    ) -> Result<Self> {
+        let headers = crate::auth::create_request_auth_headers(config, state)?;
+        Ok(Self {
+            config,
+            state,
+            state_path,
+            headers,
+        })
+    }
+
+    /// Replace a token that was rejected. If the state already holds a different token (renewed
+    /// by another session) that one is used, otherwise a new one is requested and saved in the
+    /// state file.
+    pub async fn renew(&mut self) -> Result<()> {
+        let rejected = self.headers.get(AUTHORIZATION).cloned();
+        match crate::auth::create_request_auth_headers(self.config, self.state) {
+            Ok(current) if current.get(AUTHORIZATION) != rejected.as_ref() => {
+                debug!("Token was already renewed");
+                self.headers = current;
+            }
+            _ => {
+                info!("Token was rejected, getting a new one");
+                // Forces update_auth to ask for a new token
+                self.state.auth_timeout = None;
+                crate::auth::update_auth(self.config, self.state, self.state_path.clone()).await?;
+                self.headers = crate::auth::create_request_auth_headers(self.config, self.state)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make a request with the session headers. If the token is rejected it is renewed and the
+    /// request made once more.
+    pub async fn request<T, F, Fut>(&mut self, mut request: F) -> Result<T>
+    where
+        F: FnMut(HeaderMap) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        match request(self.headers.clone()).await {
+            Err(Error::Auth(message)) => {
+                warn!("Request was not authorized ({}), renewing token", message);
+                self.renew().await?;
+                request(self.headers.clone()).await
+            }
+            ret => ret,
+        }
+    }
+}
@@ -1,21 +1,36 @@
+use crate::error::{Error, Result};
 use log::{info, trace};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use std::path::PathBuf;
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
-#[serde(tag = "type")]
+#[serde(tag = "type", content = "cursor")]
 pub enum Pagination {
     Forwards(String),
     Backwards(String),
+    /// Time a windowed listing has listed every window up to
+    Windowed(String),
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct State {
     pub auth_token: Option<String>,
     pub auth_timeout: Option<time::OffsetDateTime>,
-    pub pagination: Option<Pagination>,
+    /// Where an unfinished clip listing stopped, per `ClipQuery::key`
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub pagination: HashMap<String, Pagination>,
+    /// End of the date range of an unfinished listing that was given no end, per listing
+    #[serde(default)]
+    pub listing_ends: HashMap<String, time::OffsetDateTime>,
+    /// Cached broadcaster login -> id mappings
+    #[serde(default)]
+    pub broadcasters: HashMap<String, String>,
+    /// When each watched broadcaster was last listed
+    #[serde(default)]
+    pub last_poll: HashMap<String, time::OffsetDateTime>,
 }
 
 impl Default for State {
@@ -23,37 +38,51 @@ impl Default for State {
         Self {
             auth_token: None,
             auth_timeout: None,
-            pagination: None,
+            pagination: HashMap::new(),
+            listing_ends: HashMap::new(),
+            broadcasters: HashMap::new(),
+            last_poll: HashMap::new(),
         }
     }
 }
 
-pub fn load(path: Option<PathBuf>) -> Option<State> {
+/// Older state files stored `null` for fields that are now collections
+fn null_as_default<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Read the state file, none if it does not exist yet
+pub fn load(path: Option<PathBuf>) -> Result<Option<State>> {
     trace!("Trying to read state file");
-    if let Ok(file) = File::open(path.unwrap_or(PathBuf::from(crate::DEFAULT_STATE_LOCATION))) {
-        trace!("File opened");
-        let buf_reader = BufReader::new(file);
-
-        match serde_json::from_reader(buf_reader) {
-            Ok(read) => Some(read),
-            Err(_) => {
-                info!("Could not parse state file");
-                None
-            }
+    let path = path.unwrap_or(PathBuf::from(crate::DEFAULT_STATE_LOCATION));
+    match File::open(&path) {
+        Ok(file) => {
+            trace!("File opened");
+            let buf_reader = BufReader::new(file);
+
+            serde_json::from_reader(buf_reader)
+                .map(Some)
+                .map_err(|e| Error::Parse(format!("state file {:?}: {}", &path, e)))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!("No state file yet");
+            Ok(None)
         }
-    } else {
-        info!("Could not open state file, may not exist yet");
-        None
+        Err(e) => Err(Error::Io(path, e)),
     }
 }
 
-pub fn save(state: &State, path: Option<PathBuf>) {
+pub fn save(state: &State, path: Option<PathBuf>) -> Result<()> {
     trace!("Attempting to save state file");
 
-    let file = File::create(path.unwrap_or(PathBuf::from(crate::DEFAULT_STATE_LOCATION)))
-        .expect("Could not write to State file");
+    let path = path.unwrap_or(PathBuf::from(crate::DEFAULT_STATE_LOCATION));
+    let file = File::create(&path).map_err(Error::io(&path))?;
 
     let buf_writer = BufWriter::new(file);
 
-    serde_json::to_writer_pretty(buf_writer, state).expect("Could not serialize state");
+    serde_json::to_writer_pretty(buf_writer, state).map_err(|e| Error::Io(path, e.into()))
 }
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Deserializer};
+use time::{Date, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+/// Parse a timestamp as used by the helix api ('2020-01-31T18:30:00Z'), or a plain date
+/// ('2020-01-31') which is taken as midnight utc
+pub fn parse(input: &str) -> Result<OffsetDateTime, &'static str> {
+    let input = input.trim();
+    if let Ok(datetime) = PrimitiveDateTime::parse(input, "%FT%TZ") {
+        Ok(datetime.assume_utc())
+    } else if let Ok(datetime) = OffsetDateTime::parse(input, time::Format::Rfc3339) {
+        Ok(datetime)
+    } else if let Ok(date) = Date::parse(input, "%F") {
+        Ok(date.midnight().assume_utc())
+    } else {
+        Err("Expected a date (YYYY-MM-DD) or RFC 3339 timestamp")
+    }
+}
+
+/// Format a timestamp the way the helix api expects it
+pub fn format(datetime: OffsetDateTime) -> String {
+    datetime.to_offset(UtcOffset::UTC).format("%FT%TZ")
+}
+
+/// Deserialize an optional timestamp written in any form `parse` accepts
+pub fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(input) => parse(&input).map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,97 @@
+use crate::broadcaster::BroadcasterRef;
+use crate::error::{Error, Result};
+use crate::filter::ClipFilter;
+use crate::naming::NameTemplate;
+use log::error;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+/// Channels that are synced together by `run-all`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Watchlist {
+    pub channels: Vec<Channel>,
+}
+
+/// A channel on the watchlist and how its clips are stored
+#[derive(Debug, Clone, Deserialize)]
+pub struct Channel {
+    /// The streamer, as a login name, numeric id, or twitch.tv/<name> url
+    pub user: String,
+    /// ClipInfo file, defaults to 'clip_info/<user>.json'
+    #[serde(default)]
+    pub clips: Option<PathBuf>,
+    /// Directory clips are downloaded to, defaults to 'clips/<user>/'
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+    /// Defaults to 'name_template' in the config file
+    #[serde(default)]
+    pub name_template: Option<String>,
+    /// Only clips matching this are downloaded, all listed clips are kept in the ClipInfo file
+    #[serde(default)]
+    pub filter: ClipFilter,
+    /// List clips in date windows to get past the ~1000 clip limit of a single listing
+    #[serde(default)]
+    pub windowed: bool,
+}
+
+impl Channel {
+    pub fn broadcaster(&self) -> Result<BroadcasterRef> {
+        self.user
+            .parse()
+            .map_err(|e| Error::Parse(format!("watchlist channel {:?}: {}", &self.user, e)))
+    }
+
+    pub fn namer(&self, default: &str) -> Result<NameTemplate> {
+        self.name_template.as_deref().unwrap_or(default).parse()
+    }
+}
+
+/// A watchlist channel that has been checked and is ready to sync
+#[derive(Debug, Clone)]
+pub struct QueuedChannel {
+    /// Position in the watchlist
+    pub index: usize,
+    pub user: BroadcasterRef,
+    pub namer: NameTemplate,
+    pub channel: Channel,
+}
+
+impl Watchlist {
+    /// Check every channel up front rather than failing halfway through a run
+    pub fn queue(self, default_name_template: &str) -> Result<VecDeque<QueuedChannel>> {
+        let mut channels = VecDeque::with_capacity(self.channels.len());
+        for (index, channel) in self.channels.into_iter().enumerate() {
+            channels.push_back(QueuedChannel {
+                index,
+                user: channel.broadcaster()?,
+                namer: channel.namer(default_name_template)?,
+                channel,
+            });
+        }
+        if channels.is_empty() {
+            return Err(Error::NotFound(String::from("channels in the watchlist")));
+        }
+        Ok(channels)
+    }
+}
+
+/// Read the watchlist file, `Error::NotFound` if it does not exist
+pub fn get_watchlist(location: Option<PathBuf>) -> Result<Watchlist> {
+    let location = location.unwrap_or(PathBuf::from(crate::DEFAULT_WATCHLIST_LOCATION));
+    match File::open(&location) {
+        Ok(file) => {
+            let buf_reader = BufReader::new(file);
+            serde_json::from_reader(buf_reader).map_err(|e| {
+                error!("Could not parse watchlist file:\n{:#?}", &e);
+                Error::Parse(format!("watchlist file {:?}: {}", &location, e))
+            })
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(Error::NotFound(format!("watchlist file {:?}", &location)))
+        }
+        Err(e) => Err(Error::Io(location, e)),
+    }
+}
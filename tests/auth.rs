@@ -0,0 +1,138 @@
+mod common;
+
+use common::{MockServer, Response};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use twitch_clip_downloader::state::{self, State};
+use twitch_clip_downloader::{auth, Error};
+
+#[tokio::test]
+async fn update_auth_requests_and_saves_a_token() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/oauth2/token" => common::token_response("first-token"),
+        _ => Response::not_found(),
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("auth-new");
+    let state_path = dir.join("state.json");
+    let mut state = State::default();
+
+    auth::update_auth(&config, &mut state, Some(state_path.clone()))
+        .await
+        .expect("Could not authenticate");
+
+    assert_eq!(state.auth_token.as_deref(), Some("first-token"));
+    assert!(state.auth_timeout.is_some());
+    let saved = state::load(Some(state_path))
+        .expect("Could not read state")
+        .expect("State was not saved");
+    assert_eq!(saved.auth_token.as_deref(), Some("first-token"));
+
+    let requests = server.requests_to("/oauth2/token");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].query("client_id"), Some("mock-client-id"));
+    assert_eq!(
+        requests[0].query("client_secret"),
+        Some("mock-client-secret")
+    );
+    assert_eq!(requests[0].query("grant_type"), Some("client_credentials"));
+}
+
+#[tokio::test]
+async fn update_auth_reuses_a_valid_token() {
+    let server = MockServer::start(|_| common::token_response("first-token")).await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("auth-reuse");
+    let state_path = dir.join("state.json");
+    let mut state = State::default();
+
+    for _ in 0..3 {
+        auth::update_auth(&config, &mut state, Some(state_path.clone()))
+            .await
+            .expect("Could not authenticate");
+    }
+
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn update_auth_renews_an_expired_token() {
+    let server = MockServer::start(|_| common::token_response("second-token")).await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("auth-expired");
+    let state_path = dir.join("state.json");
+    let mut state = State {
+        auth_token: Some(String::from("first-token")),
+        auth_timeout: Some(time::OffsetDateTime::now_utc()),
+        ..State::default()
+    };
+
+    auth::update_auth(&config, &mut state, Some(state_path))
+        .await
+        .expect("Could not authenticate");
+
+    assert_eq!(state.auth_token.as_deref(), Some("second-token"));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn update_auth_retries_rate_limited_requests() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let server = MockServer::start(move |_| {
+        if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            Response::error(429, "Too Many Requests")
+        } else {
+            common::token_response("first-token")
+        }
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("auth-429");
+    let state_path = dir.join("state.json");
+    let mut state = State::default();
+
+    auth::update_auth(&config, &mut state, Some(state_path))
+        .await
+        .expect("Could not authenticate");
+
+    assert_eq!(state.auth_token.as_deref(), Some("first-token"));
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn update_auth_reports_rejected_credentials() {
+    let server = MockServer::start(|_| Response::error(403, "invalid client secret")).await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("auth-rejected");
+    let state_path = dir.join("state.json");
+    let mut state = State::default();
+
+    let ret = auth::update_auth(&config, &mut state, Some(state_path.clone())).await;
+
+    match ret {
+        Err(Error::Auth(message)) => assert_eq!(message, "invalid client secret"),
+        ret => panic!("Expected an auth error, got {:?}", ret),
+    }
+    assert_eq!(state.auth_token, None);
+    assert!(!state_path.exists());
+    // Rejected credentials are not retried
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn state_load_reports_unreadable_state() {
+    let dir = common::temp_dir("state-corrupt");
+    assert_eq!(
+        state::load(Some(dir.join("state.json"))).expect("Could not read state"),
+        None
+    );
+
+    std::fs::write(dir.join("state.json"), "{ not json").expect("Could not write state");
+    match state::load(Some(dir.join("state.json"))) {
+        Err(Error::Parse(_)) => {}
+        ret => panic!("Expected a parse error, got {:?}", ret),
+    }
+}
@@ -0,0 +1,449 @@
+mod common;
+
+use common::{MockServer, Request, Response};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use twitch_clip_downloader::broadcaster::{self, Broadcaster, BroadcasterRef};
+use twitch_clip_downloader::clip_download::{self, ClipQuery, ClipSource, Clips, ListingOptions};
+use twitch_clip_downloader::config::Config;
+use twitch_clip_downloader::session::Session;
+use twitch_clip_downloader::state::{Pagination, State};
+use twitch_clip_downloader::{auth, timestamp, ClipDownloader, Error, Result};
+
+/// A state holding a token that has not expired yet
+fn authenticated(token: &str) -> State {
+    State {
+        auth_token: Some(String::from(token)),
+        auth_timeout: Some(time::OffsetDateTime::now_utc() + time::Duration::hours(1)),
+        ..State::default()
+    }
+}
+
+/// Pages of the clips of broadcaster 1234, three clips over two pages followed by an empty one
+fn clips_page(request: &Request) -> Response {
+    match request.query("after") {
+        None => common::page(
+            vec![common::clip("a", "1234"), common::clip("b", "1234")],
+            Some("page-2"),
+        ),
+        Some("page-2") => common::page(vec![common::clip("c", "1234")], Some("page-3")),
+        Some("page-3") => common::page(Vec::new(), None),
+        Some(_) => Response::error(400, "Invalid cursor"),
+    }
+}
+
+async fn list(config: &Config, state: &mut State, dir: &PathBuf) -> Result<Clips> {
+    let client = auth::create_client_with_headers()?;
+    let mut session = Session::new(config, state, Some(dir.join("state.json")))?;
+    clip_download::get_all_clip_info(
+        ClipQuery::new(ClipSource::Broadcaster(String::from("1234"))),
+        &client,
+        &mut session,
+        dir.join("1234.partial.jsonl"),
+        &config.retry,
+    )
+    .await
+}
+
+fn ids(clips: &Clips) -> Vec<&str> {
+    clips.clips.iter().map(|clip| clip.id.as_str()).collect()
+}
+
+#[tokio::test]
+async fn get_all_clip_info_follows_cursors() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/helix/clips" => clips_page(request),
+        _ => Response::not_found(),
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("clips-cursors");
+    let mut state = authenticated("token");
+
+    let clips = list(&config, &mut state, &dir)
+        .await
+        .expect("Could not list clips");
+
+    assert_eq!(ids(&clips), vec!["a", "b", "c"]);
+    assert_eq!(clips.clips[0].name, "Clip a");
+    assert_eq!(clips.clips[0].broadcaster_id, "1234");
+
+    let requests = server.requests_to("/helix/clips");
+    assert_eq!(requests.len(), 3);
+    for request in requests.iter() {
+        assert_eq!(request.query("broadcaster_id"), Some("1234"));
+        assert_eq!(common::bearer(request), Some("token"));
+        assert_eq!(request.header("client-id"), Some("mock-client-id"));
+    }
+
+    // A completed listing leaves nothing to resume from
+    assert!(state.pagination.is_empty());
+    assert!(!dir.join("1234.partial.jsonl").exists());
+}
+
+#[tokio::test]
+async fn listings_from_a_start_date_run_until_now() {
+    let server = MockServer::start(clips_page).await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("clips-started-at");
+    let mut state = authenticated("token");
+    let client = auth::create_client_with_headers().expect("Could not create client");
+    let started_at = time::OffsetDateTime::now_utc() - time::Duration::days(30);
+
+    {
+        let mut session = Session::new(&config, &mut state, Some(dir.join("state.json")))
+            .expect("Could not start session");
+        let query = ClipQuery {
+            started_at: Some(started_at),
+            ..ClipQuery::new(ClipSource::Broadcaster(String::from("1234")))
+        };
+        clip_download::get_all_clip_info(
+            query,
+            &client,
+            &mut session,
+            dir.join("1234.partial.jsonl"),
+            &config.retry,
+        )
+        .await
+        .expect("Could not list clips");
+    }
+
+    let requests = server.requests();
+    let ended_at = requests[0]
+        .query("ended_at")
+        .expect("No end given to the listing");
+    // Every page covers the same range
+    assert!(requests
+        .iter()
+        .all(|request| request.query("ended_at") == Some(ended_at)));
+    let ended_at = timestamp::parse(ended_at).expect("Could not parse ended_at");
+    // Helix would otherwise stop a week after the start
+    assert!(ended_at > started_at + time::Duration::weeks(1));
+    assert!(state.listing_ends.is_empty());
+}
+
+#[tokio::test]
+async fn get_all_clip_info_stops_at_an_empty_page() {
+    let server = MockServer::start(|request| match request.query("after") {
+        None => common::page(vec![common::clip("a", "1234")], Some("page-2")),
+        Some(_) => common::page(Vec::new(), Some("page-3")),
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("clips-empty");
+    let mut state = authenticated("token");
+
+    let clips = list(&config, &mut state, &dir)
+        .await
+        .expect("Could not list clips");
+
+    assert_eq!(ids(&clips), vec!["a"]);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn get_all_clip_info_retries_rate_limited_pages() {
+    let limited = Arc::new(AtomicUsize::new(0));
+    let counter = limited.clone();
+    let server = MockServer::start(move |request| {
+        if request.query("after") == Some("page-2") && counter.fetch_add(1, Ordering::SeqCst) < 2 {
+            Response::error(429, "Too Many Requests")
+        } else {
+            clips_page(request)
+        }
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("clips-429");
+    let mut state = authenticated("token");
+
+    let clips = list(&config, &mut state, &dir)
+        .await
+        .expect("Could not list clips");
+
+    assert_eq!(ids(&clips), vec!["a", "b", "c"]);
+    // Two rate limited attempts and the one that went through
+    assert_eq!(limited.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn get_all_clip_info_renews_a_rejected_token() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/oauth2/token" => common::token_response("fresh-token"),
+        "/helix/clips" if common::bearer(request) == Some("fresh-token") => clips_page(request),
+        "/helix/clips" => Response::error(401, "Invalid OAuth token"),
+        _ => Response::not_found(),
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("clips-401");
+    let mut state = authenticated("revoked-token");
+
+    let clips = list(&config, &mut state, &dir)
+        .await
+        .expect("Could not list clips");
+
+    assert_eq!(ids(&clips), vec!["a", "b", "c"]);
+    assert_eq!(state.auth_token.as_deref(), Some("fresh-token"));
+    assert_eq!(server.requests_to("/oauth2/token").len(), 1);
+}
+
+#[tokio::test]
+async fn get_all_clip_info_gives_up_when_the_renewed_token_is_rejected() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/oauth2/token" => common::token_response("fresh-token"),
+        _ => Response::error(401, "Invalid OAuth token"),
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("clips-401-again");
+    let mut state = authenticated("revoked-token");
+
+    match list(&config, &mut state, &dir).await {
+        Err(Error::Auth(_)) => {}
+        ret => panic!("Expected an auth error, got {:?}", ret),
+    }
+    assert_eq!(server.requests_to("/helix/clips").len(), 2);
+}
+
+#[tokio::test]
+async fn get_all_clip_info_resumes_an_interrupted_listing() {
+    let broken = Arc::new(AtomicBool::new(true));
+    let failing = broken.clone();
+    let server = MockServer::start(move |request| {
+        if request.query("after") == Some("page-2") && failing.load(Ordering::SeqCst) {
+            Response::error(503, "Service Unavailable")
+        } else {
+            clips_page(request)
+        }
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("clips-resume");
+    let mut state = authenticated("token");
+
+    match list(&config, &mut state, &dir).await {
+        Err(Error::Api(_)) => {}
+        ret => panic!("Expected an api error, got {:?}", ret),
+    }
+    assert_eq!(
+        state.pagination.get("1234"),
+        Some(&Pagination::Forwards(String::from("page-2")))
+    );
+    assert!(dir.join("1234.partial.jsonl").exists());
+
+    broken.store(false, Ordering::SeqCst);
+    let before = server.requests().len();
+    let clips = list(&config, &mut state, &dir)
+        .await
+        .expect("Could not list clips");
+
+    assert_eq!(ids(&clips), vec!["a", "b", "c"]);
+    // The first page is not requested again
+    let resumed = &server.requests()[before..];
+    assert_eq!(resumed[0].query("after"), Some("page-2"));
+    assert!(resumed
+        .iter()
+        .all(|request| request.query("after").is_some()));
+    assert!(state.pagination.is_empty());
+}
+
+/// List the clips of broadcaster 1234 over 90 days, in three windows
+async fn list_windowed(
+    config: &Config,
+    state: &mut State,
+    dir: &PathBuf,
+    started_at: time::OffsetDateTime,
+) -> Result<Clips> {
+    let client = auth::create_client_with_headers().expect("Could not create client");
+    let mut session =
+        Session::new(config, state, Some(dir.join("state.json"))).expect("Could not start session");
+    let query = ClipQuery {
+        started_at: Some(started_at),
+        ended_at: Some(started_at + time::Duration::days(90)),
+        ..ClipQuery::new(ClipSource::Broadcaster(String::from("1234")))
+    };
+    clip_download::get_windowed_clip_info(
+        query,
+        &client,
+        &mut session,
+        dir.join("1234.partial.jsonl"),
+        &config.retry,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn get_windowed_clip_info_resumes_with_the_next_window() {
+    let started_at = timestamp::parse("2020-01-01").expect("Could not parse date");
+    let second_window = timestamp::format(started_at + time::Duration::days(30));
+    let broken = Arc::new(AtomicBool::new(true));
+    let failing = broken.clone();
+    // Each window holds a single clip named after its start
+    let server = MockServer::start(move |request| {
+        let window = request.query("started_at").unwrap_or("");
+        if window == second_window && failing.load(Ordering::SeqCst) {
+            Response::error(503, "Service Unavailable")
+        } else {
+            common::page(vec![common::clip(window, "1234")], None)
+        }
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("clips-windowed-resume");
+    let mut state = authenticated("token");
+
+    match list_windowed(&config, &mut state, &dir, started_at).await {
+        Err(Error::Api(_)) => {}
+        ret => panic!("Expected an api error, got {:?}", ret),
+    }
+    assert!(matches!(
+        state.pagination.values().next(),
+        Some(Pagination::Windowed(_))
+    ));
+    assert!(dir.join("1234.partial.jsonl").exists());
+
+    broken.store(false, Ordering::SeqCst);
+    let before = server.requests().len();
+    let clips = list_windowed(&config, &mut state, &dir, started_at)
+        .await
+        .expect("Could not list clips");
+
+    assert_eq!(clips.clips.len(), 3);
+    // The first window is not requested again
+    let first_window = timestamp::format(started_at);
+    assert!(server.requests()[before..]
+        .iter()
+        .all(|request| request.query("started_at") != Some(first_window.as_str())));
+    assert!(state.pagination.is_empty());
+    assert!(!dir.join("1234.partial.jsonl").exists());
+}
+
+#[tokio::test]
+async fn downloader_keeps_partial_listings_in_the_checkpoint_dir() {
+    let server = MockServer::start(|request| match request.query("after") {
+        Some("page-2") => Response::error(503, "Service Unavailable"),
+        _ => clips_page(request),
+    })
+    .await;
+    let dir = common::temp_dir("clips-checkpoint-dir");
+    let downloader = ClipDownloader::new(
+        common::config(&server),
+        authenticated("token"),
+        Some(dir.join("state.json")),
+    )
+    .expect("Could not create downloader")
+    .with_checkpoint_dir(dir.join("partial"));
+    let broadcaster = Broadcaster {
+        login: String::from("streamer"),
+        id: String::from("1234"),
+    };
+
+    match downloader
+        .list_clips(&broadcaster, &ListingOptions::default())
+        .await
+    {
+        Err(Error::Api(_)) => {}
+        ret => panic!("Expected an api error, got {:?}", ret),
+    }
+    assert!(dir.join("partial").join("1234.partial.jsonl").exists());
+}
+
+#[tokio::test]
+async fn broadcaster_resolve_looks_up_and_caches_logins() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/helix/users"
+            if request.query("login") == Some("streamer")
+                || request.query("id") == Some("1234") =>
+        {
+            common::page(
+                vec![serde_json::json!({ "id": "1234", "login": "streamer" })],
+                None,
+            )
+        }
+        "/helix/users" => common::page(Vec::new(), None),
+        _ => Response::not_found(),
+    })
+    .await;
+    let config = common::config(&server);
+    let dir = common::temp_dir("users");
+    let mut state = authenticated("token");
+    let client = auth::create_client_with_headers().expect("Could not create client");
+
+    {
+        let mut session = Session::new(&config, &mut state, Some(dir.join("state.json")))
+            .expect("Could not start session");
+        let login = BroadcasterRef::Login(String::from("streamer"));
+        for _ in 0..2 {
+            let resolved = broadcaster::resolve(&login, &client, &mut session)
+                .await
+                .expect("Could not resolve broadcaster");
+            assert_eq!(resolved.id, "1234");
+        }
+
+        // Ids share the files of the login
+        let id = BroadcasterRef::Id(String::from("1234"));
+        let resolved = broadcaster::resolve(&id, &client, &mut session)
+            .await
+            .expect("Could not resolve broadcaster");
+        assert_eq!(resolved.login, "streamer");
+
+        for missing in [
+            BroadcasterRef::Login(String::from("nobody")),
+            BroadcasterRef::Id(String::from("999")),
+        ]
+        .iter()
+        {
+            match broadcaster::resolve(missing, &client, &mut session).await {
+                Err(Error::NotFound(_)) => {}
+                ret => panic!("Expected not found, got {:?}", ret),
+            }
+        }
+    }
+
+    assert_eq!(
+        state.broadcasters.get("streamer").map(String::as_str),
+        Some("1234")
+    );
+    // Only the first lookup and the missing broadcasters were requested
+    assert_eq!(server.requests_to("/helix/users").len(), 3);
+}
+
+#[test]
+fn merging_a_date_range_only_removes_clips_inside_it() {
+    let clips = |dates: &[(&str, &str)]| -> Clips {
+        let clips: Vec<_> = dates
+            .iter()
+            .map(|(id, date)| {
+                serde_json::json!({
+                    "id": id,
+                    "name": "Title",
+                    "created_by": "Clipper",
+                    "created_date": date,
+                    "thumbnail_url": format!("https://clips-media-assets2.twitch.tv/{}-preview-480x272.jpg", id)
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({ "clips": clips }))
+            .expect("Could not parse clips")
+    };
+    let mut known = clips(&[
+        ("old", "2020-09-01T12:00:00Z"),
+        ("gone", "2020-10-02T12:00:00Z"),
+        ("kept", "2020-10-03T12:00:00Z"),
+    ]);
+    let listed = clips(&[
+        ("kept", "2020-10-03T12:00:00Z"),
+        ("new", "2020-10-04T12:00:00Z"),
+    ]);
+    let listing = ListingOptions {
+        started_at: Some(timestamp::parse("2020-10-01").expect("Could not parse date")),
+        ..ListingOptions::default()
+    };
+
+    let report = known.merge(listed, &listing);
+
+    assert_eq!((report.added, report.unchanged, report.removed), (1, 1, 1));
+    assert_eq!(ids(&known), vec!["old", "gone", "kept", "new"]);
+}
@@ -0,0 +1,313 @@
+//! A local stand-in for the twitch endpoints, so requests can be tested without network access.
+//!
+//! The server answers each request with whatever the handler it was started with returns and
+//! records the requests so tests can check what was asked for.
+
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use twitch_clip_downloader::config::Config;
+use twitch_clip_downloader::retry::RetryPolicy;
+
+/// A request as received by the mock server
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Header names are lowercase
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// What the mock server answers a request with
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            headers: vec![(
+                String::from("content-type"),
+                String::from("application/json"),
+            )],
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub fn bytes(status: u16, body: &[u8]) -> Self {
+        Self {
+            status,
+            headers: vec![(String::from("content-type"), String::from("video/mp4"))],
+            body: body.to_vec(),
+        }
+    }
+
+    /// A helix style error
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(
+            status,
+            json!({ "error": "Mock", "status": status, "message": message }),
+        )
+    }
+
+    pub fn not_found() -> Self {
+        Self::error(404, "Not Found")
+    }
+}
+
+pub struct MockServer {
+    /// Base url of the server, without a trailing slash
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    /// Start serving on a free local port, answering every request with `handler`
+    pub async fn start<H>(handler: H) -> Self
+    where
+        H: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let mut listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind mock server");
+        let url = format!(
+            "http://{}",
+            listener.local_addr().expect("Mock server has no address")
+        );
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let handler = Arc::new(handler);
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(_) => break,
+                };
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move { serve(stream, &*handler, &recorded).await });
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().expect("Requests poisoned").clone()
+    }
+
+    /// Requests received so far for `path`
+    pub fn requests_to(&self, path: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.path == path)
+            .collect()
+    }
+}
+
+/// Record and answer a single request, closing the connection afterwards. Requests are recorded
+/// before they are answered so they can be checked as soon as the client is done.
+async fn serve<H>(mut stream: TcpStream, handler: &H, recorded: &Mutex<Vec<Request>>) -> Option<()>
+where
+    H: Fn(&Request) -> Response,
+{
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+        if let Some(index) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break index;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?.to_string();
+
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| {
+            let colon = line.find(':')?;
+            Some((
+                line[..colon].trim().to_ascii_lowercase(),
+                line[colon + 1..].trim().to_string(),
+            ))
+        })
+        .collect();
+
+    let length: usize = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = buf[head_end + 4..].to_vec();
+    while body.len() < length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+
+    let url = reqwest::Url::parse(&format!("http://mock{}", target)).ok()?;
+    let request = Request {
+        method,
+        path: url.path().to_string(),
+        query: url.query_pairs().into_owned().collect(),
+        headers,
+        body,
+    };
+
+    let response = handler(&request);
+    recorded.lock().expect("Requests poisoned").push(request);
+
+    let reason = reqwest::StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("Mock");
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\ncontent-length: {}\r\nconnection: close\r\n",
+        response.status,
+        reason,
+        response.body.len()
+    );
+    for (name, value) in response.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&response.body).await;
+    let _ = stream.flush().await;
+    Some(())
+}
+
+/// A config pointing every request at `server`
+pub fn config(server: &MockServer) -> Config {
+    Config {
+        client_id: String::from("mock-client-id"),
+        client_secret: String::from("mock-client-secret"),
+        api_base_url: format!("{}/helix", server.url),
+        auth_base_url: format!("{}/oauth2", server.url),
+        retry: fast_retry(),
+        ..Config::default()
+    }
+}
+
+/// Retries without waiting around
+pub fn fast_retry() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        base_delay_ms: 1,
+        max_delay_ms: 5,
+        jitter: 0.0,
+        ..RetryPolicy::default()
+    }
+}
+
+/// A directory for the files of a single test, removed again when dropped. Derefs to its path.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl std::ops::Deref for TempDir {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A fresh directory for the files of a single test
+pub fn temp_dir(name: &str) -> TempDir {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut dir = std::env::temp_dir();
+    dir.push(format!(
+        "twitch-clip-downloader-{}-{}-{}",
+        name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("Could not create test directory");
+    TempDir { path: dir }
+}
+
+/// A token endpoint answer handing out `token`
+pub fn token_response(token: &str) -> Response {
+    Response::json(
+        200,
+        json!({ "access_token": token, "expires_in": 3600, "token_type": "bearer" }),
+    )
+}
+
+/// A helix clip object
+pub fn clip(id: &str, broadcaster_id: &str) -> Value {
+    json!({
+        "id": id,
+        "url": format!("https://clips.twitch.tv/{}", id),
+        "embed_url": format!("https://clips.twitch.tv/embed?clip={}", id),
+        "broadcaster_id": broadcaster_id,
+        "broadcaster_name": "Streamer",
+        "creator_id": "1000",
+        "creator_name": "Clipper",
+        "video_id": "",
+        "game_id": "33214",
+        "language": "en",
+        "title": format!("Clip {}", id),
+        "view_count": 10,
+        "created_at": "2020-10-01T12:00:00Z",
+        "thumbnail_url": format!("https://clips-media-assets2.twitch.tv/{}-preview-480x272.jpg", id),
+        "duration": 30.0
+    })
+}
+
+/// A page of helix data, with a cursor if there is a next page
+pub fn page(data: Vec<Value>, cursor: Option<&str>) -> Response {
+    let pagination = match cursor {
+        Some(cursor) => json!({ "cursor": cursor }),
+        None => json!({}),
+    };
+    Response::json(200, json!({ "data": data, "pagination": pagination }))
+}
+
+/// The bearer token a request was made with
+pub fn bearer(request: &Request) -> Option<&str> {
+    request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
@@ -0,0 +1,336 @@
+mod common;
+
+use common::{MockServer, Response};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use twitch_clip_downloader::clip_download::Clips;
+use twitch_clip_downloader::naming::NameTemplate;
+use twitch_clip_downloader::progress::{Silent, Task};
+use twitch_clip_downloader::retry::RetryPolicy;
+use twitch_clip_downloader::state::State;
+use twitch_clip_downloader::{auth, clip_download, downloader, ClipDownloader, Error, Result};
+
+/// Stand-in for the bytes of a clip
+fn video() -> Vec<u8> {
+    (0..64 * 1024).map(|i| (i % 251) as u8).collect()
+}
+
+async fn download(url: String, path: &std::path::PathBuf, retry: RetryPolicy) -> Result<()> {
+    let client = auth::create_client_with_headers()?;
+    let task: Arc<dyn Task> = Arc::new(Silent);
+    clip_download::download_clip(client, url, path.clone(), retry, task).await
+}
+
+#[tokio::test]
+async fn download_clip_saves_the_video() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/clip.mp4" => Response::bytes(200, &video()),
+        _ => Response::not_found(),
+    })
+    .await;
+    let dir = common::temp_dir("download");
+    let path = dir.join("clip.mp4");
+
+    download(
+        format!("{}/clip.mp4", server.url),
+        &path,
+        common::fast_retry(),
+    )
+    .await
+    .expect("Could not download clip");
+
+    assert_eq!(std::fs::read(&path).expect("Clip was not saved"), video());
+    assert!(!clip_download::part_path(&path).exists());
+}
+
+#[tokio::test]
+async fn download_clip_resumes_a_partial_download() {
+    let server = MockServer::start(|request| {
+        let video = video();
+        let first: usize = match request.header("range") {
+            Some(range) => range
+                .trim_start_matches("bytes=")
+                .trim_end_matches('-')
+                .parse()
+                .expect("Unexpected range"),
+            None => return Response::bytes(200, &video),
+        };
+        let mut response = Response::bytes(206, &video[first..]);
+        response.headers.push((
+            String::from("content-range"),
+            format!("bytes {}-{}/{}", first, video.len() - 1, video.len()),
+        ));
+        response
+    })
+    .await;
+    let dir = common::temp_dir("download-resume");
+    let path = dir.join("clip.mp4");
+    std::fs::write(clip_download::part_path(&path), &video()[..1000])
+        .expect("Could not write part file");
+
+    download(
+        format!("{}/clip.mp4", server.url),
+        &path,
+        common::fast_retry(),
+    )
+    .await
+    .expect("Could not download clip");
+
+    assert_eq!(std::fs::read(&path).expect("Clip was not saved"), video());
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].header("range"), Some("bytes=1000-"));
+}
+
+#[tokio::test]
+async fn download_clip_starts_over_when_the_range_is_ignored() {
+    let server = MockServer::start(|_| Response::bytes(200, &video())).await;
+    let dir = common::temp_dir("download-no-range");
+    let path = dir.join("clip.mp4");
+    std::fs::write(
+        clip_download::part_path(&path),
+        b"not the start of the clip",
+    )
+    .expect("Could not write part file");
+
+    download(
+        format!("{}/clip.mp4", server.url),
+        &path,
+        common::fast_retry(),
+    )
+    .await
+    .expect("Could not download clip");
+
+    assert_eq!(std::fs::read(&path).expect("Clip was not saved"), video());
+    // The full response is used instead of being requested again
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].header("range").is_some());
+}
+
+#[tokio::test]
+async fn download_clip_retries_failed_requests() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let server = MockServer::start(move |_| {
+        if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            Response::error(503, "Service Unavailable")
+        } else {
+            Response::bytes(200, &video())
+        }
+    })
+    .await;
+    let dir = common::temp_dir("download-retry");
+    let path = dir.join("clip.mp4");
+
+    download(
+        format!("{}/clip.mp4", server.url),
+        &path,
+        common::fast_retry(),
+    )
+    .await
+    .expect("Could not download clip");
+
+    assert_eq!(std::fs::read(&path).expect("Clip was not saved"), video());
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn download_clip_reports_missing_clips() {
+    let server = MockServer::start(|_| Response::not_found()).await;
+    let dir = common::temp_dir("download-missing");
+    let path = dir.join("clip.mp4");
+
+    match download(
+        format!("{}/clip.mp4", server.url),
+        &path,
+        common::fast_retry(),
+    )
+    .await
+    {
+        Err(Error::NotFound(_)) => {}
+        ret => panic!("Expected not found, got {:?}", ret),
+    }
+    assert!(!path.exists());
+    // Missing clips are not retried
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn downloads_are_moved_to_the_configured_cdn() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/cache/AT-cm%7C1234.mp4" => Response::bytes(200, &video()),
+        _ => Response::not_found(),
+    })
+    .await;
+    let dir = common::temp_dir("download-cdn");
+    let mut config = common::config(&server);
+    config.cdn_base_url = Some(format!("{}/cache/", server.url));
+
+    let clips: Clips = serde_json::from_value(serde_json::json!({
+        "clips": [{
+            "id": "a",
+            "name": "Clip a",
+            "created_by": "Clipper",
+            "created_date": "2020-10-01T12:00:00Z",
+            "thumbnail_url": "https://clips-media-assets2.twitch.tv/AT-cm%7C1234-preview-480x272.jpg",
+            "video_url": "https://clips-media-assets2.twitch.tv/AT-cm%7C1234.mp4?sig=abc&token=def"
+        }]
+    }))
+    .expect("Could not parse clips");
+
+    let downloader = ClipDownloader::new(config, State::default(), Some(dir.join("state.json")))
+        .expect("Could not create downloader");
+    let template: NameTemplate = "{id}.mp4".parse().expect("Could not parse template");
+    let failures = downloader
+        .download(&clips, |_| true, &dir.join("clips"), &template, 1)
+        .await
+        .expect("Could not download clips");
+
+    assert!(failures.is_empty(), "Downloads failed: {:?}", failures);
+    assert_eq!(
+        std::fs::read(dir.join("clips").join("a.mp4")).expect("Clip was not saved"),
+        video()
+    );
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].query("sig"), Some("abc"));
+    assert_eq!(requests[0].query("token"), Some("def"));
+}
+
+#[tokio::test]
+async fn download_only_removes_stale_parts_of_its_own_clips() {
+    let server = MockServer::start(|_| Response::bytes(200, &video())).await;
+    let dir = common::temp_dir("download-stale");
+    let location = dir.join("clips");
+    std::fs::create_dir_all(&location).expect("Could not create clip directory");
+
+    let clips: Clips = serde_json::from_value(serde_json::json!({
+        "clips": ["a", "b"].iter().map(|id| serde_json::json!({
+            "id": id,
+            "name": format!("Clip {}", id),
+            "created_by": "Clipper",
+            "created_date": "2020-10-01T12:00:00Z",
+            "thumbnail_url": format!("{}/{}-preview-480x272.jpg", server.url, id),
+            "video_url": format!("{}/{}.mp4", server.url, id)
+        })).collect::<Vec<_>>()
+    }))
+    .expect("Could not parse clips");
+
+    // b is not downloaded this time, the others do not belong to any of the clips
+    let stale = clip_download::part_path(&location.join("b.mp4"));
+    let foreign = clip_download::part_path(&location.join("c.mp4"));
+    let other_tool = location.join("a.mp4.part");
+    for part in [&stale, &foreign, &other_tool].iter() {
+        std::fs::write(part, &video()[..1000]).expect("Could not write part file");
+    }
+
+    let downloader = ClipDownloader::new(common::config(&server), State::default(), None)
+        .expect("Could not create downloader");
+    let template: NameTemplate = "{id}.mp4".parse().expect("Could not parse template");
+    let failures = downloader
+        .download(&clips, |clip| clip.id == "a", &location, &template, 1)
+        .await
+        .expect("Could not download clips");
+
+    assert!(failures.is_empty(), "Downloads failed: {:?}", failures);
+    assert!(location.join("a.mp4").exists());
+    assert!(!stale.exists());
+    assert!(foreign.exists());
+    assert!(other_tool.exists());
+}
+
+#[test]
+fn saved_clips_keep_their_name() {
+    let location = std::path::PathBuf::from("clips");
+    let clip = |id: &str, date: &str| {
+        serde_json::json!({
+            "id": id,
+            "name": "Same title",
+            "created_by": "Clipper",
+            "created_date": date,
+            "thumbnail_url": format!("https://clips-media-assets2.twitch.tv/{}-preview-480x272.jpg", id),
+            "video_url": format!("https://clips-media-assets2.twitch.tv/{}.mp4", id)
+        })
+    };
+    let mut clips: Clips = serde_json::from_value(serde_json::json!({
+        "clips": [clip("a", "2020-10-01T12:00:00Z"), clip("b", "2020-10-02T12:00:00Z")]
+    }))
+    .expect("Could not parse clips");
+    let template: NameTemplate = "{title}.mp4".parse().expect("Could not parse template");
+
+    // Without anything saved the oldest clip gets the plain name
+    let paths = template.paths(&location, &clips.clips);
+    assert_eq!(paths[0], Some(location.join("Same title.mp4")));
+    assert_eq!(paths[1], Some(location.join("Same title [b].mp4")));
+
+    // The newer clip was downloaded on its own before, under the plain name
+    clips.clips[1].saved_path = Some(location.join("Same title.mp4"));
+    let paths = template.paths(&location, &clips.clips);
+    assert_eq!(paths[0], Some(location.join("Same title [a].mp4")));
+    assert_eq!(paths[1], Some(location.join("Same title.mp4")));
+}
+
+#[tokio::test]
+async fn download_and_record_names_the_selection_among_all_clips() {
+    let server = MockServer::start(|_| Response::bytes(200, &video())).await;
+    let dir = common::temp_dir("download-record");
+    let location = dir.join("clips");
+    let clip = |id: &str, date: &str| {
+        serde_json::json!({
+            "id": id,
+            "name": "Same title",
+            "created_by": "Clipper",
+            "created_date": date,
+            "thumbnail_url": format!("{}/{}-preview-480x272.jpg", server.url, id),
+            "video_url": format!("{}/{}.mp4", server.url, id)
+        })
+    };
+    let mut clips: Clips = serde_json::from_value(serde_json::json!({
+        "clips": [clip("a", "2020-10-01T12:00:00Z"), clip("b", "2020-10-02T12:00:00Z")]
+    }))
+    .expect("Could not parse clips");
+
+    let downloader = ClipDownloader::new(common::config(&server), State::default(), None)
+        .expect("Could not create downloader");
+    let template: NameTemplate = "{title}.mp4".parse().expect("Could not parse template");
+    let failures = downloader
+        .download_and_record(&mut clips, |clip| clip.id == "b", &location, &template, 1)
+        .await
+        .expect("Could not download clips");
+
+    assert!(failures.is_empty(), "Downloads failed: {:?}", failures);
+    // The older clip keeps the plain name for when it is downloaded
+    let saved = location.join("Same title [b].mp4");
+    assert!(saved.exists());
+    assert!(!location.join("Same title.mp4").exists());
+    assert!(!clips.clips[0].downloaded);
+    assert!(clips.clips[1].downloaded);
+    assert_eq!(clips.clips[1].saved_path, Some(saved));
+    assert_eq!(clips.clips[1].download_error, None);
+}
+
+// Windows never allowed the old names
+#[cfg(unix)]
+#[test]
+fn clips_saved_under_legacy_names_are_found() {
+    let location = common::temp_dir("download-legacy");
+    let mut clips: Clips = serde_json::from_value(serde_json::json!({
+        "clips": [{
+            "id": "a",
+            "name": "Clip a/b",
+            "created_by": "Clipper",
+            "created_date": "2020-10-01T12:00:00Z",
+            "thumbnail_url": "https://clips-media-assets2.twitch.tv/a-offset-42-preview-480x272.jpg",
+            "video_url": "https://clips-media-assets2.twitch.tv/a-offset-42.mp4"
+        }]
+    }))
+    .expect("Could not parse clips");
+    let legacy = location.join("2020-10-01T12:00:00Z(42) Clip a-b.mp4");
+    std::fs::write(&legacy, video()).expect("Could not write clip");
+
+    downloader::mark_downloaded(&mut clips, &location, &NameTemplate::default());
+
+    assert!(clips.clips[0].downloaded);
+    assert_eq!(clips.clips[0].saved_path, Some(legacy));
+}